@@ -0,0 +1,70 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use stripper_xml::{
+    parser::parse_event, Event, Eventrecord, EventrecordReader,
+};
+
+const EVENT: &str = r#"<e>
+<se w="-0.0004385904665" muR="517.0997809" muF="517.0997809">
+<p id="1,1"> 327.0442813,0,0,327.0442813 </p>
+<p id="1,-1"> 4344.52245,0,0,-4344.52245 </p>
+<p id="0,6"> 3334.580936,-386.757619,943.5205498,-3170.151619 </p>
+<p id="0,-6"> 1336.985795,386.757619,-943.5205498,-847.3265495 </p>
+<rw ch="1"> 0.05031450481,0.6683880692,-0.0004385904665 </rw>
+</se>
+<se w="-2.098171554e-05" muR="1140.717994" muF="1140.717994">
+<p id="1,1"> 1610.067985,0,0,1610.067985 </p>
+<p id="1,-1"> 4034.720799,0,0,-4034.720799 </p>
+<p id="0,6"> 3362.889308,-2194.656814,598.8951393,-2470.642493 </p>
+<p id="0,-6"> 2281.899476,2194.656814,-598.8951393,45.98967904 </p>
+<rw ch="1"> 0.2477027669,0.6207262768,-2.098171554e-05,0.1,0.2 </rw>
+</se>
+</e>
+"#;
+
+const NEVENTS: usize = 1000;
+
+fn record() -> String {
+    let mut record = format!(
+        "<Eventrecord nevents=\"{NEVENTS}\" nsubevents=\"{}\" nreweights=\"{}\" as=\"2\" name=\"Bm\">\n",
+        2 * NEVENTS,
+        2 * NEVENTS
+    );
+    for _ in 0..NEVENTS {
+        record += EVENT;
+    }
+    record += "</Eventrecord>\n";
+    record
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("event");
+    group.bench_function("serde", |b| {
+        b.iter(|| quick_xml::de::from_str::<Event>(black_box(EVENT)).unwrap())
+    });
+    group.bench_function("parser", |b| {
+        b.iter(|| parse_event(black_box(EVENT)).unwrap())
+    });
+    group.finish();
+
+    let record = record();
+    let mut group = c.benchmark_group("record");
+    group.bench_function("serde", |b| {
+        b.iter(|| {
+            quick_xml::de::from_str::<Eventrecord>(black_box(&record)).unwrap()
+        })
+    });
+    group.bench_function("reader", |b| {
+        b.iter(|| {
+            EventrecordReader::new(black_box(record.as_bytes()))
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -0,0 +1,322 @@
+//! Running strong coupling
+//!
+//! [AlphaS] solves the renormalisation group equation for the strong
+//! coupling in the MSbar scheme numerically, starting from its value
+//! at the Z mass. The beta function is known up to four loops. The
+//! number of active flavours changes at the heavy quark masses, where
+//! the coupling is matched with the decoupling relations for MSbar
+//! masses at one loop less than the running.
+use std::f64::consts::PI;
+
+const ZETA3: f64 = 1.202_056_903_159_594_3;
+
+/// The strong coupling as a function of the scale
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct AlphaS {
+    alpha_s_mz: f64,
+    mz: f64,
+    loops: u32,
+    quark_masses: [f64; 3],
+    max_flavours: u32,
+    // a starting point `(log(mu^2), alpha_s / (4 pi))` for the
+    // evolution with 3, 4, 5, and 6 flavours
+    start: [(f64, f64); 4],
+}
+
+impl AlphaS {
+    /// Strong coupling with the given value at the Z mass
+    ///
+    /// By default, the coupling runs at four loops with at most five
+    /// active flavours. The Z mass is 91.1876 GeV and the charm,
+    /// bottom, and top masses are 1.27 GeV, 4.18 GeV, and 172.5 GeV.
+    pub fn new(alpha_s_mz: f64) -> Self {
+        Self {
+            alpha_s_mz,
+            mz: 91.1876,
+            loops: 4,
+            quark_masses: [1.27, 4.18, 172.5],
+            max_flavours: 5,
+            start: Default::default(),
+        }
+        .matched()
+    }
+
+    /// Set the number of loops in the running
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of loops is not between 1 and 4.
+    pub fn loops(mut self, loops: u32) -> Self {
+        assert!((1..=4).contains(&loops), "unsupported number of loops");
+        self.loops = loops;
+        self.matched()
+    }
+
+    /// Set the Z mass at which the coupling is given
+    pub fn mz(mut self, mz: f64) -> Self {
+        self.mz = mz;
+        self.matched()
+    }
+
+    /// Set the charm, bottom, and top quark masses
+    pub fn quark_masses(mut self, masses: [f64; 3]) -> Self {
+        self.quark_masses = masses;
+        self.matched()
+    }
+
+    /// Set the maximum number of active flavours
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of flavours is not between 3 and 6.
+    pub fn max_flavours(mut self, max_flavours: u32) -> Self {
+        assert!(
+            (3..=6).contains(&max_flavours),
+            "unsupported number of flavours"
+        );
+        self.max_flavours = max_flavours;
+        self.matched()
+    }
+
+    /// The number of active flavours at the scale `Q^2`
+    pub fn flavours_q2(&self, q2: f64) -> u32 {
+        let heavy = &self.quark_masses[..(self.max_flavours - 3) as usize];
+        3 + heavy.iter().filter(|&&m| m * m < q2).count() as u32
+    }
+
+    /// The strong coupling at the scale `Q`
+    pub fn alpha_s(&self, q: f64) -> f64 {
+        self.alpha_s_q2(q * q)
+    }
+
+    /// The strong coupling at the scale `Q^2`
+    ///
+    /// Returns NaN if `Q^2` is not positive and finite.
+    pub fn alpha_s_q2(&self, q2: f64) -> f64 {
+        if !(q2 > 0. && q2.is_finite()) {
+            return f64::NAN;
+        }
+        let nf = self.flavours_q2(q2);
+        let (t, a) = self.start[nf as usize - 3];
+        4. * PI * self.evolve(a, t, q2.ln(), nf)
+    }
+
+    // compute the starting points for each number of flavours by
+    // evolving from the Z mass across the thresholds
+    //
+    // we evolve a = alpha_s / (4 pi) in t = log(mu^2)
+    fn matched(mut self) -> Self {
+        let t_mz = (self.mz * self.mz).ln();
+        let nf_mz = self.flavours_q2(self.mz * self.mz);
+        let a_mz = self.alpha_s_mz / (4. * PI);
+        let mut start = [(t_mz, a_mz); 4];
+        let (mut t, mut a) = (t_mz, a_mz);
+        for nf in nf_mz..self.max_flavours {
+            let threshold = self.threshold(nf + 1);
+            a = self.evolve(a, t, threshold, nf);
+            a = self.decouple_up(a, nf);
+            t = threshold;
+            start[nf as usize + 1 - 3] = (t, a);
+        }
+        let (mut t, mut a) = (t_mz, a_mz);
+        for nf in (4..=nf_mz).rev() {
+            let threshold = self.threshold(nf);
+            a = self.evolve(a, t, threshold, nf);
+            a = self.decouple_down(a, nf - 1);
+            t = threshold;
+            start[nf as usize - 1 - 3] = (t, a);
+        }
+        self.start = start;
+        self
+    }
+
+    // log(m^2) for the mass of quark `flavour`
+    fn threshold(&self, flavour: u32) -> f64 {
+        let m = self.quark_masses[flavour as usize - 4];
+        (m * m).ln()
+    }
+
+    // solve the renormalisation group equation with a fixed number of
+    // flavours from t0 to t1
+    fn evolve(&self, a: f64, t0: f64, t1: f64, nf: u32) -> f64 {
+        const MAX_STEP: f64 = 0.05;
+
+        let beta = beta(nf);
+        let beta = &beta[..self.loops as usize];
+        let da_dt = |a: f64| {
+            let mut sum = 0.;
+            for b in beta.iter().rev() {
+                sum = sum * a + b;
+            }
+            -a * a * sum
+        };
+        let nsteps = ((t1 - t0).abs() / MAX_STEP).ceil().max(1.);
+        let h = (t1 - t0) / nsteps;
+        let mut a = a;
+        for _ in 0..nsteps as usize {
+            let k1 = da_dt(a);
+            let k2 = da_dt(a + h / 2. * k1);
+            let k3 = da_dt(a + h / 2. * k2);
+            let k4 = da_dt(a + h * k3);
+            a += h / 6. * (k1 + 2. * k2 + 2. * k3 + k4);
+        }
+        a
+    }
+
+    // match to `nl + 1` flavours at the threshold
+    fn decouple_up(&self, a: f64, nl: u32) -> f64 {
+        a * (1. - self.decoupling(a, nl))
+    }
+
+    // match to `nl` flavours at the threshold
+    fn decouple_down(&self, a: f64, nl: u32) -> f64 {
+        a * (1. + self.decoupling(a, nl))
+    }
+
+    // the correction in the decoupling relation at the threshold
+    fn decoupling(&self, a: f64, nl: u32) -> f64 {
+        let x = 4. * a; // alpha_s / pi
+        let c2 = 11. / 72.;
+        let c3 = 564731. / 124416.
+            - 82043. / 27648. * ZETA3
+            - 2633. / 31104. * nl as f64;
+        match self.loops {
+            1 | 2 => 0.,
+            3 => c2 * x * x,
+            _ => (c2 + c3 * x) * x * x,
+        }
+    }
+}
+
+// coefficients of the beta function, d a / d log(mu^2) = - sum_i b_i a^(i+2)
+fn beta(nf: u32) -> [f64; 4] {
+    let nf = nf as f64;
+    [
+        11. - 2. / 3. * nf,
+        102. - 38. / 3. * nf,
+        2857. / 2. - 5033. / 18. * nf + 325. / 54. * nf * nf,
+        149753. / 6. + 3564. * ZETA3
+            - (1078361. / 162. + 6508. / 27. * ZETA3) * nf
+            + (50065. / 162. + 6472. / 81. * ZETA3) * nf * nf
+            + 1093. / 729. * nf * nf * nf,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::rel_diff;
+
+    // exact solution of the renormalisation group equation at one or
+    // two loops with a fixed number of flavours, see e.g. E. Gardi,
+    // G. Grunberg, M. Karliner, JHEP 07 (1998) 007 [hep-ph/9806462]
+    //
+    // At two loops, a = alpha_s / (4 pi) satisfies
+    // b0 (t - t0) = 1/a - 1/a0 + c log(a (1 + c a0) / (a0 (1 + c a)))
+    // with c = b1 / b0, which we solve by bisection.
+    fn exact(loops: u32, alpha_s0: f64, q0: f64, q: f64, nf: u32) -> f64 {
+        let [b0, b1, ..] = beta(nf);
+        let dt = (q * q).ln() - (q0 * q0).ln();
+        let a0 = alpha_s0 / (4. * PI);
+        if loops == 1 {
+            return 4. * PI * a0 / (1. + b0 * a0 * dt);
+        }
+        let c = b1 / b0;
+        let f = |a: f64| {
+            1. / a - 1. / a0
+                + c * (a * (1. + c * a0) / (a0 * (1. + c * a))).ln()
+                - b0 * dt
+        };
+        // f decreases in a
+        let (mut lo, mut hi) = (1e-4, 1.);
+        for _ in 0..200 {
+            let mid = 0.5 * (lo + hi);
+            if f(mid) > 0. {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        4. * PI * 0.5 * (lo + hi)
+    }
+
+    // the exact solution across flavour thresholds, where the MSbar
+    // coupling is continuous at one and two loops
+    fn exact_matched(loops: u32, alpha_s: &AlphaS, q: f64) -> f64 {
+        let [mc, mb, _] = alpha_s.quark_masses;
+        let mz = alpha_s.mz;
+        let alpha_s_mz = alpha_s.alpha_s_mz;
+        if q >= mb {
+            return exact(loops, alpha_s_mz, mz, q, 5);
+        }
+        let alpha_s_mb = exact(loops, alpha_s_mz, mz, mb, 5);
+        if q >= mc {
+            return exact(loops, alpha_s_mb, mb, q, 4);
+        }
+        let alpha_s_mc = exact(loops, alpha_s_mb, mb, mc, 4);
+        exact(loops, alpha_s_mc, mc, q, 3)
+    }
+
+    #[test]
+    fn running() {
+        const SCALES: [f64; 6] = [1.2, 1.5, 3., 10., 91.1876, 150.];
+
+        for loops in 1..=2 {
+            let alpha_s = AlphaS::new(0.118).loops(loops);
+            for q in SCALES {
+                let expected = exact_matched(loops, &alpha_s, q);
+                assert!(rel_diff(alpha_s.alpha_s(q), expected) < 1e-9);
+            }
+            // six flavours above the top mass
+            let alpha_s = alpha_s.max_flavours(6);
+            let mt = 172.5;
+            let expected = exact(loops, alpha_s.alpha_s(mt), mt, 1000., 6);
+            assert!(rel_diff(alpha_s.alpha_s(1000.), expected) < 1e-9);
+        }
+
+        // at three and four loops, the MSbar coupling jumps at the
+        // thresholds according to the decoupling relation
+        // alpha_s^(nl) = alpha_s^(nl+1) (1 + c2 x^2 + c3 x^3),
+        // x = alpha_s^(nl+1) / pi, with the coefficients for MSbar
+        // masses at mu = m from K.G. Chetyrkin, B.A. Kniehl,
+        // M. Steinhauser, Nucl. Phys. B 510 (1998) 61 [hep-ph/9708255]
+        let mb = 4.18;
+        for loops in 3..=4 {
+            let alpha_s = AlphaS::new(0.118).loops(loops);
+            let above = alpha_s.alpha_s(mb * (1. + 1e-12));
+            let below = alpha_s.alpha_s(mb * (1. - 1e-12));
+            let x = above / PI;
+            let c2 = 11. / 72.;
+            let c3 = 564731. / 124416.
+                - 82043. / 27648. * ZETA3
+                - 2633. / 31104. * 4.;
+            let expected = if loops == 3 {
+                above * (1. + c2 * x * x)
+            } else {
+                above * (1. + c2 * x * x + c3 * x * x * x)
+            };
+            assert!(rel_diff(below, expected) < 1e-9);
+        }
+        // higher orders give smaller corrections
+        let at = |loops| AlphaS::new(0.118).loops(loops).alpha_s(3.);
+        let diffs = [at(2) - at(1), at(3) - at(2), at(4) - at(3)];
+        assert!(diffs[0].abs() > diffs[1].abs());
+        assert!(diffs[1].abs() > diffs[2].abs());
+
+        let alpha_s = AlphaS::new(0.118);
+        assert_eq!(alpha_s.flavours_q2(1.), 3);
+        assert_eq!(alpha_s.flavours_q2(10.), 4);
+        assert_eq!(alpha_s.flavours_q2(1e6), 5);
+        assert_eq!(alpha_s.max_flavours(6).flavours_q2(1e6), 6);
+        let alpha_s = AlphaS::new(0.120).mz(90.);
+        assert!(rel_diff(alpha_s.alpha_s(90.), 0.120) < 1e-15);
+    }
+
+    #[test]
+    fn invalid_scale() {
+        let alpha_s = AlphaS::new(0.118);
+        for q2 in [0., -1., f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(alpha_s.alpha_s_q2(q2).is_nan());
+        }
+    }
+}
@@ -1,6 +1,15 @@
-use itertools::Itertools;
+use std::fmt::{self, Display};
+use std::io::Write;
+use std::str::FromStr;
+
+use particle_id::ParticleID;
+use quick_xml::escape::escape;
 use serde::{Deserialize, Serialize, Serializer};
 
+use crate::format::WriteOptions;
+use crate::scales::ScaleChoice;
+use crate::{ParseErr, WriteXML};
+
 #[derive(
     Deserialize,
     Serialize,
@@ -15,42 +24,299 @@ use serde::{Deserialize, Serialize, Serializer};
 )]
 #[serde(rename_all = "PascalCase")]
 pub struct Init {
-    pub incoming: String,
+    pub incoming: Incoming,
     pub scales: String,
     pub channels: Channels,
 }
 
+impl Init {
+    /// Parse the scale definitions in [Init::scales]
+    pub fn scale_choice(&self) -> Result<ScaleChoice, ParseErr> {
+        self.scales.parse()
+    }
+}
+
+/// Beam particles and PDF set
+///
+/// The text representation is `<beam> <beam> with <PDF set>/<member>`,
+/// for example `p p with NNPDF31_nnlo_as_0118/0`. Beams are either
+/// given by one of the names `p`, `pbar`, `e-`, `e+` or by their PDG
+/// id.
+///
+/// The default, two protons without a PDF set, is written as an empty
+/// string.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Incoming {
+    pub beams: [ParticleID; 2],
+    pub pdf_set: String,
+    pub pdf_member: u32,
+}
+
+impl Default for Incoming {
+    fn default() -> Self {
+        use particle_id::light_baryons::proton;
+        Self {
+            beams: [proton, proton],
+            pdf_set: String::new(),
+            pdf_member: 0,
+        }
+    }
+}
+
+const BEAM_NAMES: [(&str, ParticleID); 4] = {
+    use particle_id::leptons::electron;
+    use particle_id::light_baryons::proton;
+    [
+        ("p", proton),
+        ("pbar", proton.anti()),
+        ("e-", electron),
+        ("e+", electron.anti()),
+    ]
+};
+
+fn parse_beam(beam: &str) -> Result<ParticleID, ParseErr> {
+    if let Some((_, id)) = BEAM_NAMES.iter().find(|(name, _)| *name == beam) {
+        return Ok(*id);
+    }
+    beam.parse()
+        .map(ParticleID::new)
+        .map_err(|_| ParseErr::InvalidBeam(beam.to_owned()))
+}
+
+fn write_beam(f: &mut fmt::Formatter<'_>, beam: ParticleID) -> fmt::Result {
+    match BEAM_NAMES.iter().find(|(_, id)| *id == beam) {
+        Some((name, _)) => f.write_str(name),
+        None => write!(f, "{}", beam.id()),
+    }
+}
+
+impl FromStr for Incoming {
+    type Err = ParseErr;
+
+    fn from_str(incoming_str: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseErr::InvalidIncoming(incoming_str.to_owned());
+        if incoming_str.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        let Some((beams, pdf)) = incoming_str.split_once(" with ") else {
+            return Err(invalid());
+        };
+        let mut beams = beams.split_whitespace();
+        let (Some(beam1), Some(beam2), None) =
+            (beams.next(), beams.next(), beams.next())
+        else {
+            return Err(invalid());
+        };
+        let Some((pdf_set, pdf_member)) = pdf.trim().rsplit_once('/') else {
+            return Err(invalid());
+        };
+        if pdf_set.is_empty() || pdf_set.contains(char::is_whitespace) {
+            return Err(invalid());
+        }
+        let pdf_member = pdf_member.parse().map_err(|_| invalid())?;
+        Ok(Self {
+            beams: [parse_beam(beam1)?, parse_beam(beam2)?],
+            pdf_set: pdf_set.to_owned(),
+            pdf_member,
+        })
+    }
+}
+
+impl Display for Incoming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if *self == Self::default() {
+            return Ok(());
+        }
+        write_beam(f, self.beams[0])?;
+        f.write_str(" ")?;
+        write_beam(f, self.beams[1])?;
+        write!(f, " with {}/{}", self.pdf_set, self.pdf_member)
+    }
+}
+
+impl<'de> Deserialize<'de> for Incoming {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let incoming_str = String::deserialize(deserializer)?;
+        incoming_str.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Incoming {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Partonic channels
+///
+/// The channel indices are contiguous, so that the channel of a
+/// [Reweight](crate::Reweight) entry can be looked up quickly with
+/// [Channels::get].
 #[derive(
-    Deserialize,
-    Serialize,
-    Clone,
-    Debug,
-    Default,
-    Eq,
-    PartialEq,
-    Ord,
-    PartialOrd,
-    Hash,
+    Serialize, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash,
 )]
 #[serde(rename_all = "PascalCase")]
 pub struct Channels {
-    pub channel: Vec<Channel>,
+    channel: Vec<Channel>,
 }
 
+impl Channels {
+    /// Create the list of channels
+    ///
+    /// The channel indices have to be contiguous.
+    pub fn new(channel: Vec<Channel>) -> Result<Self, ParseErr> {
+        let channels = Self { channel };
+        channels.check_contiguous()?;
+        Ok(channels)
+    }
+
+    /// Iterate over the channels in the order of their indices
+    pub fn iter(&self) -> std::slice::Iter<'_, Channel> {
+        self.channel.iter()
+    }
+
+    /// The number of channels
+    pub fn len(&self) -> usize {
+        self.channel.len()
+    }
+
+    /// Whether there are no channels
+    pub fn is_empty(&self) -> bool {
+        self.channel.is_empty()
+    }
+
+    /// Look up the channel with the given index
+    ///
+    /// The index is the one given by [Reweight::channel](crate::Reweight::channel).
+    pub fn get(&self, index: u32) -> Option<&Channel> {
+        let first = self.channel.first()?.index;
+        let pos = index.checked_sub(first)?;
+        self.channel.get(pos as usize)
+    }
+
+    fn check_contiguous(&self) -> Result<(), ParseErr> {
+        let Some(first) = self.channel.first() else {
+            return Ok(());
+        };
+        for (expected, channel) in (first.index..).zip(&self.channel) {
+            if channel.index != expected {
+                return Err(ParseErr::NonContiguousChannel {
+                    expected,
+                    found: channel.index,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for Channels {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct ChannelList {
+            #[serde(default)]
+            channel: Vec<Channel>,
+        }
+
+        let ChannelList { channel } = ChannelList::deserialize(deserializer)?;
+        Self::new(channel).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A partonic channel
+///
+/// The text representation is `<index>,<npairs>,<a1>,<b1>,<a2>,<b2>,...`,
+/// where `a` and `b` are the PDG ids of the partons in each pair.
 #[derive(Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
-pub struct Channel(pub Vec<i32>);
+pub struct Channel {
+    index: u32,
+    pairs: Vec<(ParticleID, ParticleID)>,
+}
+
+impl Channel {
+    /// Create a channel with the given index and parton pairs
+    pub fn new(index: u32, pairs: Vec<(ParticleID, ParticleID)>) -> Self {
+        Self { index, pairs }
+    }
+
+    /// The channel index
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// The number of parton pairs
+    pub fn npairs(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// The parton pairs contributing to the channel
+    pub fn pairs(
+        &self,
+    ) -> impl ExactSizeIterator<Item = (ParticleID, ParticleID)> + '_ {
+        self.pairs.iter().copied()
+    }
+}
+
+impl FromStr for Channel {
+    type Err = ParseErr;
+
+    fn from_str(channel_str: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseErr::InvalidChannelDef(channel_str.to_owned());
+        let mut entries = channel_str.split(',').map(str::trim);
+        let (Some(index), Some(npairs)) = (entries.next(), entries.next())
+        else {
+            return Err(invalid());
+        };
+        let index = index.parse().map_err(|_| invalid())?;
+        let npairs: usize = npairs.parse().map_err(|_| invalid())?;
+        let ids: Vec<i32> = entries
+            .map(|id| id.parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        if !ids.len().is_multiple_of(2) {
+            return Err(invalid());
+        }
+        if ids.len() / 2 != npairs {
+            return Err(ParseErr::NumPairs {
+                channel: index,
+                declared: npairs,
+                actual: ids.len() / 2,
+            });
+        }
+        let pairs = ids
+            .chunks_exact(2)
+            .map(|pair| (ParticleID::new(pair[0]), ParticleID::new(pair[1])))
+            .collect();
+        Ok(Self { index, pairs })
+    }
+}
+
+impl Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{}", self.index, self.npairs())?;
+        for (a, b) in self.pairs() {
+            write!(f, ",{},{}", a.id(), b.id())?;
+        }
+        Ok(())
+    }
+}
 
 impl<'de> Deserialize<'de> for Channel {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        use serde::de::Error;
         let channel_str = String::deserialize(deserializer)?;
-        let entries: Result<Vec<i32>, _> =
-            channel_str.split(',').map(|e| e.parse()).collect();
-        let entries = entries.map_err(Error::custom)?;
-        Ok(Self(entries))
+        channel_str.parse().map_err(serde::de::Error::custom)
     }
 }
 
@@ -59,8 +325,47 @@ impl Serialize for Channel {
     where
         S: Serializer,
     {
-        let res = self.0.iter().map(ToString::to_string).join(",");
-        serializer.serialize_str(&res)
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl WriteXML for Init {
+    type Error = std::io::Error;
+
+    fn write_with<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &WriteOptions,
+    ) -> Result<(), Self::Error> {
+        writeln!(writer, "<Init>")?;
+        writeln!(
+            writer,
+            "  <Incoming> {} </Incoming>",
+            escape(self.incoming.to_string().as_str())
+        )?;
+        writeln!(
+            writer,
+            "  <Scales> {} </Scales>",
+            escape(self.scales.as_str())
+        )?;
+        self.channels.write_with(writer, options)?;
+        writeln!(writer, "</Init>")
+    }
+}
+
+impl WriteXML for Channels {
+    type Error = std::io::Error;
+
+    fn write_with<W: Write>(
+        &self,
+        writer: &mut W,
+        _options: &WriteOptions,
+    ) -> Result<(), Self::Error> {
+        writeln!(writer, "  <Channels>")?;
+        for channel in self.iter() {
+            writeln!(writer, "    <Channel> {channel} </Channel>")?;
+        }
+        writeln!(writer, "  </Channels>")
     }
 }
 
@@ -68,9 +373,7 @@ impl Serialize for Channel {
 mod tests {
     use super::*;
 
-    #[test]
-    fn deser_init() {
-        const REF_CHANNELS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+    const REF_CHANNELS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
 <Init>
   <Incoming> p p with NNPDF31_nnlo_as_0118/0 </Incoming>
   <Scales> muR = HT, muF = HT </Scales>
@@ -91,10 +394,127 @@ mod tests {
   </Channels>
 </Init>
 "#;
+
+    #[test]
+    fn deser_init() {
+        let init: Init = quick_xml::de::from_str(REF_CHANNELS).unwrap();
+        assert_eq!(init.channels.len(), 13);
+        let channel = init.channels.iter().nth(5).unwrap();
+        assert_eq!(channel.index(), 5);
+        let pairs: Vec<_> =
+            channel.pairs().map(|(a, b)| [a.id(), b.id()]).collect();
+        assert_eq!(pairs, [[-1, 1], [-2, 2], [-3, 3], [-4, 4], [-5, 5]]);
+        let scales = init.scale_choice().unwrap();
+        assert_eq!(scales.to_string(), "muR = HT, muF = HT");
+    }
+
+    #[test]
+    fn channel_lookup() {
+        let init: Init = quick_xml::de::from_str(REF_CHANNELS).unwrap();
+        let channels = &init.channels;
+        let rw = crate::Reweight {
+            channel: 12,
+            ..Default::default()
+        };
+        let channel = channels.get(rw.channel).unwrap();
+        assert_eq!(channel.index(), 12);
+        let gluon = ParticleID::new(21);
+        assert_eq!(channel.pairs().collect::<Vec<_>>(), [(gluon, gluon)]);
+        assert_eq!(channels.get(13), None);
+
+        assert!(matches!(
+            "3,2,1,-1".parse::<Channel>(),
+            Err(ParseErr::NumPairs {
+                channel: 3,
+                declared: 2,
+                actual: 1
+            })
+        ));
+        assert!(matches!(
+            "3,1,1".parse::<Channel>(),
+            Err(ParseErr::InvalidChannelDef(_))
+        ));
+        let gaps = REF_CHANNELS.replace("<Channel> 5,", "<Channel> 13,");
+        assert!(quick_xml::de::from_str::<Init>(&gaps).is_err());
+
+        let channels = Channels::new(vec![
+            Channel::new(3, vec![(gluon, gluon)]),
+            Channel::new(4, vec![]),
+        ])
+        .unwrap();
+        assert_eq!(channels.get(3).unwrap().index(), 3);
+        assert_eq!(channels.get(4).unwrap().index(), 4);
+        assert_eq!(channels.get(5), None);
+        assert_eq!(channels.get(2), None);
+        assert!(matches!(
+            Channels::new(vec![
+                Channel::new(3, vec![]),
+                Channel::new(7, vec![])
+            ]),
+            Err(ParseErr::NonContiguousChannel {
+                expected: 4,
+                found: 7
+            })
+        ));
+    }
+
+    #[test]
+    fn parse_incoming() {
+        use particle_id::light_baryons::proton;
+
         let init: Init = quick_xml::de::from_str(REF_CHANNELS).unwrap();
         assert_eq!(
-            init.channels.channel[5].0,
-            [5, 5, -1, 1, -2, 2, -3, 3, -4, 4, -5, 5]
+            init.incoming,
+            Incoming {
+                beams: [proton, proton],
+                pdf_set: "NNPDF31_nnlo_as_0118".to_owned(),
+                pdf_member: 0
+            }
         );
+
+        let incoming: Incoming = "pbar 2212 with CT18NNLO/12".parse().unwrap();
+        assert_eq!(incoming.beams, [proton.anti(), proton]);
+        assert_eq!(incoming.pdf_member, 12);
+        assert_eq!(incoming.to_string(), "pbar p with CT18NNLO/12");
+        let incoming: Incoming = "e- 11 with x/1".parse().unwrap();
+        assert_eq!(incoming.beams[0], incoming.beams[1]);
+        assert_eq!(Incoming::default().to_string(), "");
+        assert_eq!(" ".parse::<Incoming>().unwrap(), Incoming::default());
+
+        for invalid in [
+            "p p",
+            "p with NNPDF31_nnlo_as_0118/0",
+            "p p with NNPDF31_nnlo_as_0118",
+            "p p with NNPDF31_nnlo_as_0118/x",
+            "p p with /0",
+        ] {
+            assert!(matches!(
+                invalid.parse::<Incoming>(),
+                Err(ParseErr::InvalidIncoming(_))
+            ));
+        }
+        assert!(matches!(
+            "p x with NNPDF31_nnlo_as_0118/0".parse::<Incoming>(),
+            Err(ParseErr::InvalidBeam(_))
+        ));
+    }
+
+    #[test]
+    fn ser_init() {
+        let init: Init = quick_xml::de::from_str(REF_CHANNELS).unwrap();
+        let mut out = br#"<?xml version="1.0" encoding="UTF-8"?>
+"#
+        .to_vec();
+        init.write(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), REF_CHANNELS);
+
+        let init = Init::default();
+        let mut out = Vec::new();
+        init.write(&mut out).unwrap();
+        let parsed: Init = quick_xml::de::from_reader(out.as_slice()).unwrap();
+        assert_eq!(parsed, init);
+        let txt = quick_xml::se::to_string(&init).unwrap();
+        let parsed: Init = quick_xml::de::from_str(&txt).unwrap();
+        assert_eq!(parsed, init);
     }
 }
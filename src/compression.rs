@@ -0,0 +1,382 @@
+//! Transparent compression for record files
+//!
+//! Readers detect the compression format from the leading magic
+//! bytes, writers choose it from the file extension or an explicit
+//! [Compression]. Support for each format is behind the cargo feature
+//! of the same name: `gzip`, `zstd`, `bzip2`.
+//!
+//! Compressed streams are completed when a [Compressor] is dropped,
+//! but errors at that point are lost. Call [Compressor::finish] to
+//! handle them.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const BZIP2_MAGIC: &[u8] = b"BZh";
+
+impl Compression {
+    /// Choose the compression from the file extension
+    ///
+    /// Recognised extensions are `gz`, `zst` or `zstd`, and `bz2`.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let Some(ext) = path.as_ref().extension() else {
+            return Self::None;
+        };
+        match ext.to_str() {
+            Some("gz") => Self::Gzip,
+            Some("zst" | "zstd") => Self::Zstd,
+            Some("bz2") => Self::Bzip2,
+            _ => Self::None,
+        }
+    }
+
+    /// Detect the compression from the magic bytes at the start of the input
+    pub fn detect(start: &[u8]) -> Self {
+        if start.starts_with(GZIP_MAGIC) {
+            Self::Gzip
+        } else if start.starts_with(ZSTD_MAGIC) {
+            Self::Zstd
+        } else if start.starts_with(BZIP2_MAGIC) {
+            Self::Bzip2
+        } else {
+            Self::None
+        }
+    }
+}
+
+fn unsupported(feature: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{feature} compression requires the `{feature}` feature"),
+    )
+}
+
+/// Reader decompressing its input
+pub enum Decompressor<R: BufRead> {
+    Plain(R),
+    #[cfg(feature = "gzip")]
+    Gzip(BufReader<flate2::bufread::MultiGzDecoder<R>>),
+    #[cfg(feature = "zstd")]
+    Zstd(BufReader<zstd::Decoder<'static, R>>),
+    #[cfg(feature = "bzip2")]
+    Bzip2(BufReader<bzip2::bufread::MultiBzDecoder<R>>),
+}
+
+/// Wrap a reader, decompressing the input if necessary
+///
+/// The compression format is detected from the first bytes of the input.
+pub fn decompress<R: BufRead>(mut reader: R) -> io::Result<Decompressor<R>> {
+    match Compression::detect(reader.fill_buf()?) {
+        Compression::None => Ok(Decompressor::Plain(reader)),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Ok(Decompressor::Gzip(BufReader::new(
+            flate2::bufread::MultiGzDecoder::new(reader),
+        ))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(Decompressor::Zstd(BufReader::new(
+            zstd::Decoder::with_buffer(reader)?,
+        ))),
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => Ok(Decompressor::Bzip2(BufReader::new(
+            bzip2::bufread::MultiBzDecoder::new(reader),
+        ))),
+        #[allow(unreachable_patterns)]
+        compression => Err(unsupported(feature_name(compression))),
+    }
+}
+
+/// Open a file for reading, decompressing its contents if necessary
+pub fn open(
+    path: impl AsRef<Path>,
+) -> io::Result<Decompressor<BufReader<File>>> {
+    decompress(BufReader::new(File::open(path)?))
+}
+
+impl<R: BufRead> Read for Decompressor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(r) => r.read(buf),
+            #[cfg(feature = "gzip")]
+            Self::Gzip(r) => r.read(buf),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(r) => r.read(buf),
+            #[cfg(feature = "bzip2")]
+            Self::Bzip2(r) => r.read(buf),
+        }
+    }
+}
+
+impl<R: BufRead> BufRead for Decompressor<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match self {
+            Self::Plain(r) => r.fill_buf(),
+            #[cfg(feature = "gzip")]
+            Self::Gzip(r) => r.fill_buf(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(r) => r.fill_buf(),
+            #[cfg(feature = "bzip2")]
+            Self::Bzip2(r) => r.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self {
+            Self::Plain(r) => r.consume(amt),
+            #[cfg(feature = "gzip")]
+            Self::Gzip(r) => r.consume(amt),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(r) => r.consume(amt),
+            #[cfg(feature = "bzip2")]
+            Self::Bzip2(r) => r.consume(amt),
+        }
+    }
+}
+
+/// Writer compressing its output
+///
+/// The compressed stream is completed when the writer is dropped,
+/// ignoring any errors. Call [finish](Self::finish) after the last
+/// write to detect them.
+#[must_use = "call `finish` to detect errors when completing the stream"]
+pub enum Compressor<W: Write> {
+    Plain(W),
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<W>),
+    #[cfg(feature = "zstd")]
+    Zstd(ZstdEncoder<W>),
+    #[cfg(feature = "bzip2")]
+    Bzip2(bzip2::write::BzEncoder<W>),
+}
+
+/// Wrap a writer, compressing the output with the given format
+pub fn compress<W: Write>(
+    writer: W,
+    compression: Compression,
+) -> io::Result<Compressor<W>> {
+    match compression {
+        Compression::None => Ok(Compressor::Plain(writer)),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Ok(Compressor::Gzip(
+            flate2::write::GzEncoder::new(writer, Default::default()),
+        )),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(Compressor::Zstd(ZstdEncoder(Some(
+            zstd::Encoder::new(writer, 0)?,
+        )))),
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => Ok(Compressor::Bzip2(
+            bzip2::write::BzEncoder::new(writer, Default::default()),
+        )),
+        #[allow(unreachable_patterns)]
+        compression => Err(unsupported(feature_name(compression))),
+    }
+}
+
+/// Create a file for writing, with compression chosen by the extension
+pub fn create(
+    path: impl AsRef<Path>,
+) -> io::Result<Compressor<BufWriter<File>>> {
+    let compression = Compression::from_path(&path);
+    create_with(path, compression)
+}
+
+/// Create a file for writing with the given compression
+pub fn create_with(
+    path: impl AsRef<Path>,
+    compression: Compression,
+) -> io::Result<Compressor<BufWriter<File>>> {
+    compress(BufWriter::new(File::create(path)?), compression)
+}
+
+impl<W: Write> Compressor<W> {
+    /// Finish the compressed stream and return the underlying writer
+    pub fn finish(self) -> io::Result<W> {
+        let mut writer = match self {
+            Self::Plain(w) => w,
+            #[cfg(feature = "gzip")]
+            Self::Gzip(w) => w.finish()?,
+            #[cfg(feature = "zstd")]
+            Self::Zstd(w) => w.into_inner().finish()?,
+            #[cfg(feature = "bzip2")]
+            Self::Bzip2(w) => w.finish()?,
+        };
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+impl<W: Write> Write for Compressor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(w) => w.write(buf),
+            #[cfg(feature = "gzip")]
+            Self::Gzip(w) => w.write(buf),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(w) => w.get_mut().write(buf),
+            #[cfg(feature = "bzip2")]
+            Self::Bzip2(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(w) => w.flush(),
+            #[cfg(feature = "gzip")]
+            Self::Gzip(w) => w.flush(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(w) => w.get_mut().flush(),
+            #[cfg(feature = "bzip2")]
+            Self::Bzip2(w) => w.flush(),
+        }
+    }
+}
+
+/// zstd encoder completing the stream when dropped
+///
+/// Unlike [zstd::Encoder], this matches the behaviour of the gzip and
+/// bzip2 encoders.
+#[cfg(feature = "zstd")]
+pub struct ZstdEncoder<W: Write>(Option<zstd::Encoder<'static, W>>);
+
+#[cfg(feature = "zstd")]
+impl<W: Write> ZstdEncoder<W> {
+    fn get_mut(&mut self) -> &mut zstd::Encoder<'static, W> {
+        self.0.as_mut().unwrap()
+    }
+
+    fn into_inner(mut self) -> zstd::Encoder<'static, W> {
+        self.0.take().unwrap()
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<W: Write> Drop for ZstdEncoder<W> {
+    fn drop(&mut self) {
+        if let Some(encoder) = self.0.take() {
+            let _ = encoder.finish();
+        }
+    }
+}
+
+fn feature_name(compression: Compression) -> &'static str {
+    match compression {
+        Compression::None => "",
+        Compression::Gzip => "gzip",
+        Compression::Zstd => "zstd",
+        Compression::Bzip2 => "bzip2",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{INIT, NORMALIZATION, RECORD};
+    use crate::{channels::Init, normalization::Normalization, Eventrecord};
+
+    #[test]
+    fn extension() {
+        assert_eq!(Compression::from_path("a.xml"), Compression::None);
+        assert_eq!(Compression::from_path("a.xml.gz"), Compression::Gzip);
+        assert_eq!(Compression::from_path("a.xml.zst"), Compression::Zstd);
+        assert_eq!(Compression::from_path("a.xml.zstd"), Compression::Zstd);
+        assert_eq!(Compression::from_path("a.xml.bz2"), Compression::Bzip2);
+    }
+
+    fn compressed(txt: &str, compression: Compression) -> Vec<u8> {
+        let mut writer = compress(Vec::new(), compression).unwrap();
+        writer.write_all(txt.as_bytes()).unwrap();
+        let compressed = writer.finish().unwrap();
+        assert_eq!(Compression::detect(&compressed), compression);
+        compressed
+    }
+
+    fn round_trip(compression: Compression) {
+        let init = compressed(INIT, compression);
+        let init: Init =
+            quick_xml::de::from_reader(decompress(init.as_slice()).unwrap())
+                .unwrap();
+        assert_eq!(init.channels.len(), 2);
+
+        let norm = compressed(NORMALIZATION, compression);
+        let norm: Normalization =
+            quick_xml::de::from_reader(decompress(norm.as_slice()).unwrap())
+                .unwrap();
+        assert_eq!(norm.name, "Bm");
+
+        let record = compressed(RECORD, compression);
+        let record: Eventrecord =
+            quick_xml::de::from_reader(decompress(record.as_slice()).unwrap())
+                .unwrap();
+        assert_eq!(record.events.len(), 3);
+    }
+
+    #[test]
+    fn plain() {
+        round_trip(Compression::None)
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip() {
+        round_trip(Compression::Gzip)
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd() {
+        round_trip(Compression::Zstd)
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn bzip2() {
+        round_trip(Compression::Bzip2)
+    }
+
+    #[test]
+    fn finish_on_drop() {
+        let compressions = [
+            Compression::None,
+            #[cfg(feature = "gzip")]
+            Compression::Gzip,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd,
+            #[cfg(feature = "bzip2")]
+            Compression::Bzip2,
+        ];
+        for compression in compressions {
+            let mut compressed = Vec::new();
+            {
+                let mut writer =
+                    compress(&mut compressed, compression).unwrap();
+                writer.write_all(INIT.as_bytes()).unwrap();
+            }
+            let mut decompressed = String::new();
+            decompress(compressed.as_slice())
+                .unwrap()
+                .read_to_string(&mut decompressed)
+                .unwrap();
+            assert_eq!(decompressed, INIT);
+        }
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    #[test]
+    fn gzip_unsupported() {
+        let err = compress(Vec::new(), Compression::Gzip).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        let err = decompress([0x1f, 0x8b, 0].as_slice()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+}
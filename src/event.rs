@@ -1,9 +1,17 @@
+use std::fmt::{self, Display};
+use std::io::{BufRead, Read, Seek, Write};
+use std::str::FromStr;
+
 use particle_id::ParticleID;
+use quick_xml::events::{BytesStart, Event as XmlEvent};
 use serde::{Deserialize, Serialize, Serializer};
 use serde_repr::*;
 use strum::EnumString;
 use thiserror::Error;
 
+use crate::format::WriteOptions;
+use crate::version::FormatVersion;
+
 #[derive(
     Deserialize, Serialize, Clone, Debug, Default, PartialEq, PartialOrd,
 )]
@@ -20,6 +28,68 @@ pub struct Eventrecord {
     pub name: String,
     #[serde(rename = "e")]
     pub events: Vec<Event>,
+    /// The format version, if known
+    ///
+    /// The version is detected from the comments preceding the first
+    /// event. It is `None` for records deserialised with serde, which
+    /// skips comments. Use [Eventrecord::from_reader] to detect it.
+    #[serde(skip)]
+    pub version: Option<FormatVersion>,
+    // content outside the STRIPPER format, only kept in lossless mode
+    #[serde(skip)]
+    pub(crate) extra: ExtraContent,
+}
+
+impl Eventrecord {
+    /// Read a record, detecting its format version
+    ///
+    /// The result is the same as deserialising with serde, except for
+    /// the [version](Self::version).
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, ReadErr> {
+        let mut reader = EventrecordReader::new(reader)?;
+        let events = reader.by_ref().collect::<Result<_, _>>()?;
+        let header = reader.header().clone();
+        Ok(Self {
+            nevents: header.nevents,
+            nsubevents: header.nsubevents,
+            nreweights: header.nreweights,
+            alpha_s_power: header.alpha_s_power,
+            name: header.name,
+            events,
+            version: Some(header.version),
+            extra: header.extra,
+        })
+    }
+
+    /// The attributes of the `<Eventrecord>` element
+    ///
+    /// An unknown [version](Self::version) is taken to be the
+    /// [latest](FormatVersion::LATEST) one.
+    pub fn header(&self) -> EventrecordHeader {
+        EventrecordHeader {
+            nevents: self.nevents,
+            nsubevents: self.nsubevents,
+            nreweights: self.nreweights,
+            alpha_s_power: self.alpha_s_power,
+            name: self.name.clone(),
+            version: self.version.clone().unwrap_or(FormatVersion::LATEST),
+            extra: self.extra.clone(),
+        }
+    }
+
+    /// Record-level content outside the STRIPPER format
+    ///
+    /// This is only filled in when reading in
+    /// [lossless](EventrecordReader::lossless) mode and contains the
+    /// same content as the [header](EventrecordHeader::extra).
+    pub fn extra(&self) -> &ExtraContent {
+        &self.extra
+    }
+
+    /// Mutable access to the record-level content outside the STRIPPER format
+    pub fn extra_mut(&mut self) -> &mut ExtraContent {
+        &mut self.extra
+    }
 }
 
 #[derive(
@@ -29,6 +99,24 @@ pub struct Eventrecord {
 pub struct Event {
     #[serde(rename = "se")]
     pub subevents: Vec<SubEvent>,
+    // content outside the STRIPPER format, only kept in lossless mode
+    #[serde(skip)]
+    pub(crate) extra: EventExtra,
+}
+
+impl Event {
+    /// Content outside the STRIPPER format
+    ///
+    /// This is only filled in when reading in
+    /// [lossless](EventrecordReader::lossless) mode.
+    pub fn extra(&self) -> &EventExtra {
+        &self.extra
+    }
+
+    /// Mutable access to the content outside the STRIPPER format
+    pub fn extra_mut(&mut self) -> &mut EventExtra {
+        &mut self.extra
+    }
 }
 
 #[derive(
@@ -48,6 +136,154 @@ pub struct SubEvent {
     pub reweight: Vec<Reweight>,
 }
 
+/// Content of an event that is not part of the STRIPPER format
+///
+/// This includes the content of the `<e>` element and of each of its
+/// `<se>` elements.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct EventExtra {
+    /// Unknown attributes and children of the `<e>` element
+    pub event: ExtraContent,
+    /// Unknown attributes and children of the `<se>` elements
+    ///
+    /// The entries are in the order of the subevents. Trailing
+    /// subevents without extra content may not have an entry.
+    pub subevents: Vec<ExtraContent>,
+}
+
+impl EventExtra {
+    /// Whether there is no extra content
+    pub fn is_empty(&self) -> bool {
+        self.event.is_empty() && self.subevents.iter().all(|s| s.is_empty())
+    }
+
+    /// The extra content of the subevent with the given index
+    pub fn subevent(&self, index: usize) -> &ExtraContent {
+        const EMPTY: &ExtraContent = &ExtraContent {
+            attributes: Vec::new(),
+            children: Vec::new(),
+        };
+        self.subevents.get(index).unwrap_or(EMPTY)
+    }
+
+    // set the extra content of the subevent with the given index,
+    // only adding entries for non-empty content
+    pub(crate) fn set_subevent(&mut self, index: usize, extra: ExtraContent) {
+        if index < self.subevents.len() {
+            self.subevents[index] = extra;
+        } else if !extra.is_empty() {
+            self.subevents.resize_with(index, Default::default);
+            self.subevents.push(extra);
+        }
+    }
+}
+
+/// Content that is not part of the STRIPPER format
+///
+/// This is only filled in when reading in
+/// [lossless](EventrecordReader::lossless) mode and written back
+/// unchanged. Attribute values and elements are kept exactly as in
+/// the input, i.e. without unescaping.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ExtraContent {
+    /// Unknown attributes as `(name, value)` pairs
+    pub attributes: Vec<(String, String)>,
+    /// Comments and unknown child elements in the order of the input
+    pub children: Vec<ExtraChild>,
+}
+
+/// A comment or unknown element inside a known element
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ExtraChild {
+    /// The number of known child elements preceding this one
+    ///
+    /// The known children are the events of an `<Eventrecord>`, the
+    /// subevents of an `<e>`, and the particles followed by the
+    /// reweighting entries of an `<se>`.
+    pub position: usize,
+    pub node: ExtraNode,
+}
+
+/// Content of an [ExtraChild]
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum ExtraNode {
+    /// A comment, without the `<!--` and `-->` delimiters
+    Comment(String),
+    /// An unknown element
+    Element(String),
+}
+
+impl Display for ExtraNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Comment(comment) => write!(f, "<!--{comment}-->"),
+            Self::Element(element) => f.write_str(element),
+        }
+    }
+}
+
+impl ExtraContent {
+    /// Whether there is no extra content
+    pub fn is_empty(&self) -> bool {
+        self.attributes.is_empty() && self.children.is_empty()
+    }
+
+    /// The comments in the order of the input
+    pub fn comments(&self) -> impl Iterator<Item = &str> {
+        self.children.iter().filter_map(|child| match &child.node {
+            ExtraNode::Comment(comment) => Some(comment.as_str()),
+            ExtraNode::Element(_) => None,
+        })
+    }
+
+    /// The unknown elements in the order of the input
+    pub fn elements(&self) -> impl Iterator<Item = &str> {
+        self.children.iter().filter_map(|child| match &child.node {
+            ExtraNode::Element(element) => Some(element.as_str()),
+            ExtraNode::Comment(_) => None,
+        })
+    }
+
+    pub(crate) fn push(&mut self, position: usize, node: ExtraNode) {
+        self.children.push(ExtraChild { position, node });
+    }
+
+    fn write_attributes(&self, out: &mut String) {
+        for (name, value) in &self.attributes {
+            out.push(' ');
+            out.push_str(name);
+            out.push_str("=\"");
+            out.push_str(value);
+            out.push('"');
+        }
+    }
+
+    // the children starting at index `*next` that precede the known
+    // child at `position`, advancing `*next` past them
+    fn children_before(
+        &self,
+        next: &mut usize,
+        position: usize,
+    ) -> &[ExtraChild] {
+        let rest = &self.children[*next..];
+        let n = rest.iter().take_while(|c| c.position <= position).count();
+        *next += n;
+        &rest[..n]
+    }
+
+    fn write_children_before<W: Write>(
+        &self,
+        writer: &mut W,
+        next: &mut usize,
+        position: usize,
+    ) -> std::io::Result<()> {
+        for child in self.children_before(next, position) {
+            writeln!(writer, "{}", child.node)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, PartialOrd)]
 #[serde(rename = "p")]
 pub struct Particle {
@@ -105,32 +341,34 @@ pub enum Status {
 #[derive(Clone, Debug, Default, PartialEq, PartialOrd)]
 pub struct Momentum(pub [f64; 4]);
 
-impl<'de> Deserialize<'de> for Momentum {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let momentum_str = String::deserialize(deserializer)?;
+impl FromStr for Momentum {
+    type Err = ParseErr;
+
+    fn from_str(momentum_str: &str) -> Result<Self, Self::Err> {
         let mut entries = momentum_str.split(',');
         let mut momentum = [0.; 4];
         for q in &mut momentum {
             let Some(p) = entries.next() else {
-                return Err(serde::de::Error::custom(ParseErr::NumEntries(
-                    momentum_str,
-                    4,
-                )));
+                return Err(ParseErr::NumEntries(momentum_str.to_owned(), 4));
             };
-            *q = p.parse().map_err(serde::de::Error::custom)?;
+            *q = parse_float(p)?;
         }
         if entries.next().is_some() {
-            return Err(serde::de::Error::custom(ParseErr::NumEntries(
-                momentum_str,
-                4,
-            )));
+            return Err(ParseErr::NumEntries(momentum_str.to_owned(), 4));
         }
         Ok(Self(momentum))
     }
 }
+
+impl<'de> Deserialize<'de> for Momentum {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let momentum_str = String::deserialize(deserializer)?;
+        momentum_str.parse().map_err(serde::de::Error::custom)
+    }
+}
 impl Serialize for Momentum {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -142,31 +380,35 @@ impl Serialize for Momentum {
     }
 }
 
-impl<'de> Deserialize<'de> for Reweights {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        use serde::de::Error;
+impl FromStr for Reweights {
+    type Err = ParseErr;
+
+    fn from_str(reweights_str: &str) -> Result<Self, Self::Err> {
         use ParseErr::NumEntries;
-        let reweights_str = String::deserialize(deserializer)?;
         let mut reweights = reweights_str.split(',');
         let Some(x1) = reweights.next() else {
-            return Err(Error::custom(NumEntries(reweights_str, 2)));
+            return Err(NumEntries(reweights_str.to_owned(), 2));
         };
-        let x1 = x1.parse().map_err(Error::custom)?;
+        let x1 = parse_float(x1)?;
         let Some(x2) = reweights.next() else {
-            return Err(Error::custom(NumEntries(reweights_str, 2)));
+            return Err(NumEntries(reweights_str.to_owned(), 2));
         };
-        let x2 = x2.parse().map_err(Error::custom)?;
-        let mut log_coeff = Vec::new();
-        for val in reweights {
-            log_coeff.push(val.parse().map_err(Error::custom)?);
-        }
+        let x2 = parse_float(x2)?;
+        let log_coeff = reweights.map(parse_float).collect::<Result<_, _>>()?;
         Ok(Self { x1, x2, log_coeff })
     }
 }
 
+impl<'de> Deserialize<'de> for Reweights {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let reweights_str = String::deserialize(deserializer)?;
+        reweights_str.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 impl Serialize for Reweights {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -181,37 +423,45 @@ impl Serialize for Reweights {
     }
 }
 
-impl<'de> Deserialize<'de> for Id {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let id_str = String::deserialize(deserializer)?;
+impl FromStr for Id {
+    type Err = ParseErr;
+
+    fn from_str(id_str: &str) -> Result<Self, Self::Err> {
         let mut entries = id_str.split(',');
         let Some(status) = entries.next() else {
-            return Err(serde::de::Error::custom(ParseErr::NumEntries(
-                id_str, 2,
-            )));
+            return Err(ParseErr::NumEntries(id_str.to_owned(), 2));
         };
-        let status = status.parse().map_err(serde::de::Error::custom)?;
+        let status = status
+            .trim()
+            .parse()
+            .map_err(|_| ParseErr::InvalidStatus(status.to_owned()))?;
 
         let Some(pdg_id) = entries.next() else {
-            return Err(serde::de::Error::custom(ParseErr::NumEntries(
-                id_str, 2,
-            )));
+            return Err(ParseErr::NumEntries(id_str.to_owned(), 2));
         };
-        let pdg_id: i32 = pdg_id.parse().map_err(serde::de::Error::custom)?;
+        let pdg_id: i32 = pdg_id
+            .trim()
+            .parse()
+            .map_err(|_| ParseErr::InvalidPdgCode(pdg_id.to_owned()))?;
         let pdg_id = ParticleID::new(pdg_id);
 
         if entries.next().is_some() {
-            return Err(serde::de::Error::custom(ParseErr::NumEntries(
-                id_str, 2,
-            )));
+            return Err(ParseErr::NumEntries(id_str.to_owned(), 2));
         }
 
         Ok(Self { status, pdg_id })
     }
 }
+
+impl<'de> Deserialize<'de> for Id {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let id_str = String::deserialize(deserializer)?;
+        id_str.parse().map_err(serde::de::Error::custom)
+    }
+}
 impl Serialize for Id {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -234,30 +484,35 @@ pub trait WriteXML {
     fn write<W: std::io::Write>(
         &self,
         writer: &mut W,
+    ) -> Result<(), Self::Error> {
+        self.write_with(writer, &WriteOptions::default())
+    }
+
+    fn write_with<W: std::io::Write>(
+        &self,
+        writer: &mut W,
+        options: &WriteOptions,
     ) -> Result<(), Self::Error>;
 }
 
 impl WriteXML for Eventrecord {
     type Error = std::io::Error;
 
-    fn write<W: std::io::Write>(
+    fn write_with<W: std::io::Write>(
         &self,
         writer: &mut W,
+        options: &WriteOptions,
     ) -> Result<(), Self::Error> {
-        writeln!(
-            writer,
-            "<Eventrecord nevents=\"{}\" nsubevents=\"{}\" nreweights=\"{}\" as=\"{}\" name=\"{}\">",
-            self.nevents, self.nsubevents, self.nreweights, self.alpha_s_power, self.name
-        )?;
-        writeln!(
-            writer,
-            "<!--\nRecord generated with {} {}\n-->",
-            env!("CARGO_PKG_NAME"),
-            env!("CARGO_PKG_VERSION")
-        )?;
-        for event in &self.events {
-            event.write(writer)?;
+        let header = self.header();
+        header.write_start(writer, 0)?;
+        let mut next = 0;
+        header.write_preamble(writer, &mut next)?;
+        for (n, event) in self.events.iter().enumerate() {
+            self.extra.write_children_before(writer, &mut next, n)?;
+            event.write_with(writer, options)?;
         }
+        self.extra
+            .write_children_before(writer, &mut next, usize::MAX)?;
         writer.write_all(b"</Eventrecord>\n")
     }
 }
@@ -265,14 +520,23 @@ impl WriteXML for Eventrecord {
 impl WriteXML for Event {
     type Error = std::io::Error;
 
-    fn write<W: std::io::Write>(
+    fn write_with<W: std::io::Write>(
         &self,
         writer: &mut W,
+        options: &WriteOptions,
     ) -> Result<(), Self::Error> {
-        writer.write_all(b"<e>\n")?;
-        for subevent in &self.subevents {
-            subevent.write(writer)?;
+        let extra = &self.extra.event;
+        let mut start = "<e".to_owned();
+        extra.write_attributes(&mut start);
+        start.push_str(">\n");
+        writer.write_all(start.as_bytes())?;
+        let mut next = 0;
+        for (n, subevent) in self.subevents.iter().enumerate() {
+            extra.write_children_before(writer, &mut next, n)?;
+            let subevent_extra = self.extra.subevent(n);
+            subevent.write_with_extra(writer, options, subevent_extra)?;
         }
+        extra.write_children_before(writer, &mut next, usize::MAX)?;
         writer.write_all(b"</e>\n")
     }
 }
@@ -280,37 +544,1202 @@ impl WriteXML for Event {
 impl WriteXML for SubEvent {
     type Error = std::io::Error;
 
-    fn write<W: std::io::Write>(
+    fn write_with<W: std::io::Write>(
         &self,
         writer: &mut W,
+        options: &WriteOptions,
     ) -> Result<(), Self::Error> {
+        self.write_with_extra(writer, options, &ExtraContent::default())
+    }
+}
+
+impl SubEvent {
+    // write the subevent, including content outside the STRIPPER format
+    fn write_with_extra<W: std::io::Write>(
+        &self,
+        writer: &mut W,
+        options: &WriteOptions,
+        extra: &ExtraContent,
+    ) -> std::io::Result<()> {
         use std::fmt::Write;
 
+        let mut out = String::new();
+        out.push_str("<se w=\"");
+        options.write_float(&mut out, self.weight);
+        out.push_str("\" muR=\"");
+        options.write_float(&mut out, self.mu_r);
+        out.push_str("\" muF=\"");
+        options.write_float(&mut out, self.mu_f);
+        out.push('"');
+        extra.write_attributes(&mut out);
+        out.push_str(">\n");
+        let mut next = 0;
+        let mut write_extra = |out: &mut String, position| {
+            for child in extra.children_before(&mut next, position) {
+                writeln!(out, "{}", child.node).unwrap();
+            }
+        };
+        let nparticles = self.particles.len();
+        for (n, p) in self.particles.iter().enumerate() {
+            write_extra(&mut out, n);
+            write!(
+                out,
+                "<p id=\"{},{}\">",
+                p.id.status as u8,
+                p.id.pdg_id.id()
+            )
+            .unwrap();
+            options.write_floats(&mut out, p.momentum.0);
+            out.push_str("</p>\n");
+        }
+        for (n, rw) in self.reweight.iter().enumerate() {
+            write_extra(&mut out, nparticles + n);
+            write!(out, "<rw ch=\"{}\">", rw.channel).unwrap();
+            let Reweights { x1, x2, log_coeff } = &rw.reweights;
+            options.write_floats(
+                &mut out,
+                [*x1, *x2].into_iter().chain(log_coeff.iter().copied()),
+            );
+            out.push_str("</rw>\n");
+        }
+        write_extra(&mut out, usize::MAX);
+        out.push_str("</se>\n");
+        writer.write_all(out.as_bytes())
+    }
+}
+
+/// Attributes of the `<Eventrecord>` element
+#[derive(Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct EventrecordHeader {
+    pub nevents: u64,
+    pub nsubevents: u64,
+    pub nreweights: u64,
+    pub alpha_s_power: u64,
+    pub name: String,
+    /// The format version
+    ///
+    /// This is not an attribute, but detected from the comments
+    /// preceding the first event.
+    pub version: FormatVersion,
+    /// Content outside the STRIPPER format, only kept in lossless mode
+    ///
+    /// This includes the comments and unknown elements at the record
+    /// level. Their [position](ExtraChild::position) is the number of
+    /// preceding events.
+    pub extra: ExtraContent,
+}
+
+impl EventrecordHeader {
+    // write the opening `<Eventrecord>` tag
+    //
+    // Each count attribute is padded with trailing spaces to at least
+    // `count_width` characters, so that it can be overwritten later
+    fn write_start<W: Write>(
+        &self,
+        writer: &mut W,
+        count_width: usize,
+    ) -> std::io::Result<()> {
+        writer.write_all(b"<Eventrecord ")?;
+        self.write_counts(writer, count_width)?;
+        let mut extra = String::new();
+        self.extra.write_attributes(&mut extra);
         writeln!(
             writer,
-            "<se w=\"{}\" muR=\"{}\" muF=\"{}\">",
-            self.weight, self.mu_r, self.mu_f
-        )?;
-        let mut out = String::new();
-        for p in &self.particles {
-            let ser = quick_xml::se::Serializer::new(&mut out);
-            p.serialize(ser).unwrap();
-            out.write_char('\n').unwrap();
+            "as=\"{}\" name=\"{}\"{extra}>",
+            self.alpha_s_power, self.name
+        )
+    }
+
+    // write the record-level comments and elements preceding the first
+    // event, with the generator comment if there are no comments
+    fn write_preamble<W: Write>(
+        &self,
+        writer: &mut W,
+        next: &mut usize,
+    ) -> std::io::Result<()> {
+        if self.extra.comments().next().is_none() {
+            write_generator_comment(writer)?;
         }
-        for rw in &self.reweight {
-            let ser = quick_xml::se::Serializer::new(&mut out);
-            rw.serialize(ser).unwrap();
-            out.write_char('\n').unwrap();
+        self.extra.write_children_before(writer, next, 0)
+    }
+
+    fn write_counts<W: Write>(
+        &self,
+        writer: &mut W,
+        count_width: usize,
+    ) -> std::io::Result<()> {
+        for (name, count) in [
+            ("nevents", self.nevents),
+            ("nsubevents", self.nsubevents),
+            ("nreweights", self.nreweights),
+        ] {
+            let count = count.to_string();
+            let pad = count_width.saturating_sub(count.len());
+            write!(writer, "{name}=\"{count}\" {:pad$}", "")?;
+        }
+        Ok(())
+    }
+
+    /// Read only the header of a record
+    ///
+    /// The input is read up to the end of the comments following the
+    /// opening `<Eventrecord>` tag, which is typically only the first
+    /// few hundred bytes. The events are not read.
+    ///
+    /// As in [lossless](EventrecordReader::lossless) mode, the comments,
+    /// including the generator comment, and unknown attributes are
+    /// kept in [extra](Self::extra). The [version](Self::version) is
+    /// detected from the comments.
+    pub fn peek<R: BufRead>(reader: R) -> Result<Self, ReadErr> {
+        let mut reader = quick_xml::Reader::from_reader(reader);
+        let mut buf = Vec::new();
+        let mut header = Self::read(&mut reader, &mut buf)?;
+        loop {
+            buf.clear();
+            match reader.read_event_into(&mut buf)? {
+                XmlEvent::Comment(comment) => {
+                    let comment = String::from_utf8_lossy(&comment);
+                    let comment = ExtraNode::Comment(comment.into_owned());
+                    header.extra.push(0, comment);
+                }
+                XmlEvent::Text(text)
+                    if text.iter().all(u8::is_ascii_whitespace) => {}
+                _ => break,
+            }
+        }
+        header.version = FormatVersion::detect(header.extra.comments());
+        Ok(header)
+    }
+
+    /// Read only the header of a record file
+    ///
+    /// Compressed files are decompressed as needed, see
+    /// [compression::open](crate::compression::open). See
+    /// [peek](Self::peek) for details.
+    pub fn peek_file(
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<Self, ReadErr> {
+        Self::peek(crate::compression::open(path)?)
+    }
+
+    // read up to and including the opening `<Eventrecord>` tag
+    pub(crate) fn read<R: BufRead>(
+        reader: &mut quick_xml::Reader<R>,
+        buf: &mut Vec<u8>,
+    ) -> Result<Self, ReadErr> {
+        loop {
+            buf.clear();
+            match reader.read_event_into(buf)? {
+                XmlEvent::Start(start)
+                    if start.local_name().as_ref() == b"Eventrecord" =>
+                {
+                    return Self::from_start(&start);
+                }
+                XmlEvent::Start(_) | XmlEvent::Empty(_) | XmlEvent::Eof => {
+                    return Err(ReadErr::NoEventrecord)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn from_start(start: &BytesStart) -> Result<Self, ReadErr> {
+        let mut nevents = None;
+        let mut nsubevents = None;
+        let mut nreweights = None;
+        let mut alpha_s_power = None;
+        let mut name = None;
+        let mut extra = ExtraContent::default();
+        for attr in start.attributes() {
+            let attr = attr.map_err(quick_xml::Error::from)?;
+            let value = attr.unescape_value()?;
+            let (key, entry) = match attr.key.local_name().as_ref() {
+                b"nevents" => ("nevents", &mut nevents),
+                b"nsubevents" => ("nsubevents", &mut nsubevents),
+                b"nreweights" => ("nreweights", &mut nreweights),
+                b"as" => ("as", &mut alpha_s_power),
+                b"name" => {
+                    name = Some(value.into_owned());
+                    continue;
+                }
+                _ => {
+                    extra.attributes.push((
+                        String::from_utf8_lossy(attr.key.as_ref()).into_owned(),
+                        String::from_utf8_lossy(&attr.value).into_owned(),
+                    ));
+                    continue;
+                }
+            };
+            let Ok(value) = value.trim().parse() else {
+                return Err(ReadErr::InvalidAttribute(key, value.into_owned()));
+            };
+            *entry = Some(value);
         }
-        writer.write_all(out.as_bytes())?;
-        writer.write_all(b"</se>\n")
+        Ok(Self {
+            nevents: nevents.ok_or(ReadErr::MissingAttribute("nevents"))?,
+            nsubevents: nsubevents
+                .ok_or(ReadErr::MissingAttribute("nsubevents"))?,
+            nreweights: nreweights
+                .ok_or(ReadErr::MissingAttribute("nreweights"))?,
+            alpha_s_power: alpha_s_power
+                .ok_or(ReadErr::MissingAttribute("as"))?,
+            name: name.ok_or(ReadErr::MissingAttribute("name"))?,
+            version: FormatVersion::default(),
+            extra,
+        })
     }
 }
 
+/// Streaming reader for event records
+///
+/// The `<Eventrecord>` attributes are parsed on construction, after
+/// which the reader yields one [Event] at a time, so that memory use
+/// does not grow with the size of the record.
+pub struct EventrecordReader<R> {
+    reader: quick_xml::Reader<LineCounter<R>>,
+    header: EventrecordHeader,
+    buf: Vec<u8>,
+    event_buf: Vec<u8>,
+    done: bool,
+    seek_offset: u64,
+    seek_position: usize,
+    nevents: u64,
+    // the number of subevents and reweighting entries read so far,
+    // unknown after skipping events or seeking
+    nsubevents: Option<u64>,
+    nreweights: Option<u64>,
+    event_start: Location,
+    lenient: bool,
+    allow_truncated: bool,
+    truncated: bool,
+    check_counts: bool,
+    lossless: bool,
+    // unknown `<Eventrecord>` attributes and content before the first
+    // event, only moved to the header in lossless mode
+    preamble: ExtraContent,
+    // the result of reading ahead while looking for record-level content
+    pending: Option<Result<XmlEvent<'static>, ReadErr>>,
+    // whether the reader has seeked, so that the open elements known
+    // to the XML reader no longer match the input
+    seeked: bool,
+    diagnostics: Diagnostics,
+}
+
+impl<R: BufRead> EventrecordReader<R> {
+    /// Construct a new reader, parsing the record header
+    pub fn new(reader: R) -> Result<Self, ReadErr> {
+        let reader = LineCounter {
+            inner: reader,
+            pos: LinePosition::START,
+        };
+        let mut reader = quick_xml::Reader::from_reader(reader);
+        let mut buf = Vec::new();
+        let header = EventrecordHeader::read(&mut reader, &mut buf)?;
+        let mut reader = Self {
+            reader,
+            header,
+            buf,
+            event_buf: Vec::new(),
+            done: false,
+            seek_offset: 0,
+            seek_position: 0,
+            nevents: 0,
+            nsubevents: Some(0),
+            nreweights: Some(0),
+            event_start: Location::default(),
+            lenient: false,
+            allow_truncated: false,
+            truncated: false,
+            check_counts: false,
+            lossless: false,
+            preamble: ExtraContent::default(),
+            pending: None,
+            seeked: false,
+            diagnostics: Diagnostics::default(),
+        };
+        // errors are reported when reading the first event
+        let next = reader.read_preamble();
+        reader.pending = Some(next);
+        let extra = std::mem::take(&mut reader.header.extra);
+        reader.header.version = FormatVersion::detect(extra.comments());
+        reader.preamble = extra;
+        Ok(reader)
+    }
+
+    /// Keep content that is not part of the STRIPPER format
+    ///
+    /// Comments, unknown attributes, and unknown child elements of
+    /// `<Eventrecord>`, `<e>`, and `<se>` are kept as [ExtraContent]
+    /// so that they can be written back. Record-level content
+    /// preceding the first event is added to the [header](Self::header)
+    /// when this option is enabled. Later record-level content is
+    /// added to the header as it is encountered and can be passed on
+    /// to [EventrecordWriter::update_extra].
+    pub fn lossless(mut self, lossless: bool) -> Self {
+        self.lossless = lossless;
+        if lossless {
+            self.header.extra = std::mem::take(&mut self.preamble);
+        }
+        self
+    }
+
+    /// Skip malformed events instead of returning an error
+    ///
+    /// Each skipped event is recorded in the [diagnostics](Self::diagnostics).
+    /// Only events that are well-formed XML, but contain invalid
+    /// entries, can be skipped.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Accept input that ends before the end of the record
+    ///
+    /// This happens for example when the program writing the record
+    /// was killed. All complete events are returned, any incomplete
+    /// event at the end of the input is discarded and the record is
+    /// flagged as [truncated](Self::is_truncated).
+    pub fn allow_truncated(mut self, allow_truncated: bool) -> Self {
+        self.allow_truncated = allow_truncated;
+        self
+    }
+
+    /// Check that the record matches the counts declared in its header
+    ///
+    /// If enabled, reaching the end of a record with a different
+    /// number of events, subevents, or reweighting entries than
+    /// declared results in a [ParseErr::HeaderMismatch] error. Only
+    /// the number of events is checked after skipping malformed
+    /// events or seeking.
+    pub fn check_counts(mut self, check_counts: bool) -> Self {
+        self.check_counts = check_counts;
+        self
+    }
+
+    /// Whether the input ended before the end of the record
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Statistics on the events read so far
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    /// The attributes of the `<Eventrecord>` element
+    pub fn header(&self) -> &EventrecordHeader {
+        &self.header
+    }
+
+    /// The current byte offset in the input
+    pub fn position(&self) -> u64 {
+        let read = self.reader.buffer_position() - self.seek_position;
+        self.seek_offset + read as u64
+    }
+
+    // the number of events, subevents, and reweighting entries read
+    // so far, where the latter two are unknown after skipping events
+    // or seeking
+    pub(crate) fn counts(&self) -> (u64, Option<u64>, Option<u64>) {
+        (self.nevents, self.nsubevents, self.nreweights)
+    }
+
+    // enable or disable the count check, returning the previous setting
+    pub(crate) fn set_check_counts(&mut self, check_counts: bool) -> bool {
+        std::mem::replace(&mut self.check_counts, check_counts)
+    }
+
+    fn read_event(&mut self) -> Result<Option<Event>, ReadErr> {
+        loop {
+            match self.read_raw_event() {
+                Err(ReadErr::Parse(err)) if self.lenient => {
+                    self.nsubevents = None;
+                    self.nreweights = None;
+                    self.diagnostics.skipped.push(SkippedEvent {
+                        index: self.nevents - 1,
+                        position: self.event_start.offset,
+                        err,
+                    })
+                }
+                Err(ReadErr::UnexpectedEof | ReadErr::Xml(_))
+                    if self.allow_truncated && self.at_eof() =>
+                {
+                    self.truncated = true;
+                    return Ok(None);
+                }
+                Ok(Some(event)) => {
+                    self.diagnostics.nread += 1;
+                    if let Some(n) = &mut self.nsubevents {
+                        *n += event.subevents.len() as u64;
+                    }
+                    if let Some(n) = &mut self.nreweights {
+                        *n += count_reweights(&event);
+                    }
+                    return Ok(Some(event));
+                }
+                Ok(None) if self.check_counts => {
+                    self.verify_counts()?;
+                    return Ok(None);
+                }
+                res => return res,
+            }
+        }
+    }
+
+    fn verify_counts(&self) -> Result<(), LocatedParseErr> {
+        let counts = [
+            ("nevents", self.header.nevents, Some(self.nevents)),
+            ("nsubevents", self.header.nsubevents, self.nsubevents),
+            ("nreweights", self.header.nreweights, self.nreweights),
+        ];
+        for (attribute, declared, actual) in counts {
+            match actual {
+                Some(actual) if actual != declared => {
+                    return Err(LocatedParseErr {
+                        location: self.location(),
+                        kind: ParseErr::HeaderMismatch {
+                            attribute,
+                            declared,
+                            actual,
+                        },
+                    })
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // the location of the current position in the input
+    fn location(&self) -> Location {
+        let pos = self.reader.get_ref().pos;
+        Location {
+            offset: self.position(),
+            line: pos.line,
+            column: pos.line.map(|_| pos.column + 1),
+            ..Default::default()
+        }
+    }
+
+    fn at_eof(&mut self) -> bool {
+        self.reader
+            .get_mut()
+            .fill_buf()
+            .is_ok_and(|buf| buf.is_empty())
+    }
+
+    // read record-level comments and elements up to the first event
+    //
+    // Returns the first XML event that is not part of the preamble
+    fn read_preamble(&mut self) -> Result<XmlEvent<'static>, ReadErr> {
+        loop {
+            self.buf.clear();
+            let event = self.reader.read_event_into(&mut self.buf)?;
+            let extra = &mut self.header.extra;
+            if !Self::record_extra(&mut self.reader, extra, 0, &event)? {
+                return Ok(event.into_owned());
+            }
+        }
+    }
+
+    // add record-level comments and unknown elements at the given
+    // position to the header
+    //
+    // Returns whether the XML event was consumed
+    fn record_extra(
+        reader: &mut quick_xml::Reader<LineCounter<R>>,
+        extra: &mut ExtraContent,
+        position: usize,
+        event: &XmlEvent,
+    ) -> Result<bool, ReadErr> {
+        match event {
+            XmlEvent::Comment(comment) => {
+                let comment = String::from_utf8_lossy(comment);
+                extra.push(position, ExtraNode::Comment(comment.into_owned()));
+            }
+            XmlEvent::Text(text)
+                if text.iter().all(u8::is_ascii_whitespace) => {}
+            XmlEvent::Empty(start) if start.local_name().as_ref() != b"e" => {
+                let element = format!("<{}/>", String::from_utf8_lossy(start));
+                extra.push(position, ExtraNode::Element(element));
+            }
+            XmlEvent::Start(start) if start.local_name().as_ref() != b"e" => {
+                let mut element = Vec::new();
+                let mut writer = quick_xml::Writer::new(&mut element);
+                writer.write_event(XmlEvent::Start(start.borrow()))?;
+                Self::copy_element(reader, &mut writer)?;
+                let element = String::from_utf8_lossy(&element).into_owned();
+                extra.push(position, ExtraNode::Element(element));
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn read_raw_event(&mut self) -> Result<Option<Event>, ReadErr> {
+        loop {
+            let Self {
+                reader,
+                buf,
+                event_buf,
+                pending,
+                ..
+            } = self;
+            buf.clear();
+            // after seeking, the closing `</Eventrecord>` tag does not
+            // match any element known to the XML reader
+            reader.check_end_names(!self.seeked);
+            let event = match pending.take() {
+                Some(event) => event?,
+                None => reader.read_event_into(buf)?,
+            };
+            match event {
+                XmlEvent::Start(start)
+                    if start.local_name().as_ref() == b"e" =>
+                {
+                    // all elements inside the event are known again
+                    reader.check_end_names(true);
+                    let len = start.len() + "<>".len();
+                    let offset = self.seek_offset
+                        + (reader.buffer_position() - self.seek_position - len)
+                            as u64;
+                    // the line counter is positioned after the start tag
+                    let mut pos = reader.get_ref().pos;
+                    if start.contains(&b'\n') {
+                        pos.line = None;
+                    }
+                    self.event_start = Location {
+                        event: Some(self.nevents),
+                        subevent: None,
+                        offset,
+                        line: pos.line,
+                        column: pos.line.map(|_| pos.column + 1 - len as u64),
+                    };
+                    self.nevents += 1;
+                    event_buf.clear();
+                    let mut writer = quick_xml::Writer::new(&mut *event_buf);
+                    writer.write_event(XmlEvent::Start(start))?;
+                    Self::copy_element(reader, &mut writer)?;
+                    let event =
+                        std::str::from_utf8(event_buf).map_err(|err| {
+                            quick_xml::Error::NonDecodable(Some(err))
+                        })?;
+                    let event =
+                        crate::parser::parse_event_with(event, self.lossless)
+                            .map_err(|err| err.offset_by(&self.event_start))?;
+                    return Ok(Some(event));
+                }
+                XmlEvent::Empty(start)
+                    if start.local_name().as_ref() == b"e" =>
+                {
+                    self.nevents += 1;
+                    if !self.lossless {
+                        return Ok(Some(Event::default()));
+                    }
+                    // keep unknown attributes
+                    let event =
+                        format!("<{}/>", String::from_utf8_lossy(&start));
+                    let event = crate::parser::parse_event_with(&event, true)?;
+                    return Ok(Some(event));
+                }
+                XmlEvent::End(end)
+                    if end.local_name().as_ref() == b"Eventrecord" =>
+                {
+                    return Ok(None);
+                }
+                XmlEvent::Eof => return Err(ReadErr::UnexpectedEof),
+                event if self.lossless => {
+                    reader.check_end_names(true);
+                    let extra = &mut self.header.extra;
+                    let position = self.nevents as usize;
+                    Self::record_extra(reader, extra, position, &event)?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // copy XML events up to and including the end of the current element
+    fn copy_element(
+        reader: &mut quick_xml::Reader<LineCounter<R>>,
+        writer: &mut quick_xml::Writer<&mut Vec<u8>>,
+    ) -> Result<(), ReadErr> {
+        let mut buf = Vec::new();
+        let mut depth = 1;
+        while depth > 0 {
+            buf.clear();
+            let event = reader.read_event_into(&mut buf)?;
+            match event {
+                XmlEvent::Start(_) => depth += 1,
+                XmlEvent::End(_) => depth -= 1,
+                XmlEvent::Eof => return Err(ReadErr::UnexpectedEof),
+                _ => {}
+            }
+            writer.write_event(event)?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead + Seek> EventrecordReader<R> {
+    // Continue reading at the given byte offset, which has to be
+    // the start of an element
+    pub(crate) fn seek_to(
+        &mut self,
+        offset: u64,
+        nevents: u64,
+    ) -> std::io::Result<()> {
+        let reader = self.reader.get_mut();
+        reader.inner.seek(std::io::SeekFrom::Start(offset))?;
+        reader.pos.line = None;
+        self.seeked = true;
+        self.seek_offset = offset;
+        self.seek_position = self.reader.buffer_position();
+        self.nevents = nevents;
+        self.nsubevents = None;
+        self.nreweights = None;
+        self.pending = None;
+        self.done = false;
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for EventrecordReader<R> {
+    type Item = Result<Event, ReadErr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let res = self.read_event();
+        if !matches!(res, Ok(Some(_))) {
+            self.done = true;
+        }
+        res.transpose()
+    }
+}
+
+/// Statistics on the events read by an [EventrecordReader]
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    nread: u64,
+    skipped: Vec<SkippedEvent>,
+}
+
+impl Diagnostics {
+    /// The number of events read successfully
+    pub fn nread(&self) -> u64 {
+        self.nread
+    }
+
+    /// The number of skipped events
+    pub fn nskipped(&self) -> u64 {
+        self.skipped.len() as u64
+    }
+
+    /// The total number of events encountered
+    pub fn ntotal(&self) -> u64 {
+        self.nread() + self.nskipped()
+    }
+
+    /// The skipped events
+    pub fn skipped(&self) -> &[SkippedEvent] {
+        &self.skipped
+    }
+}
+
+/// A malformed event skipped by a lenient [EventrecordReader]
+#[derive(Debug)]
+pub struct SkippedEvent {
+    /// Index of the event in the record, starting from zero
+    pub index: u64,
+    /// Byte offset of the `<e>` element in the input
+    pub position: u64,
+    /// The reason why the event could not be parsed
+    pub err: LocatedParseErr,
+}
+
+// Input wrapper keeping track of the line and column
+struct LineCounter<R> {
+    inner: R,
+    pos: LinePosition,
+}
+
+impl<R: Read> Read for LineCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.pos.advance(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for LineCounter<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // the consumed data is still in the buffer
+        if let Ok(buf) = self.inner.fill_buf() {
+            self.pos.advance(&buf[..amt.min(buf.len())]);
+        }
+        self.inner.consume(amt)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct LinePosition {
+    // line number starting from one, `None` if unknown
+    line: Option<u64>,
+    // bytes since the start of the line
+    column: u64,
+}
+
+impl LinePosition {
+    const START: Self = Self {
+        line: Some(1),
+        column: 0,
+    };
+
+    fn advance(&mut self, text: &[u8]) {
+        match text.iter().rposition(|&b| b == b'\n') {
+            Some(last) => {
+                let newlines = text.iter().filter(|&&b| b == b'\n').count();
+                self.line = self.line.map(|line| line + newlines as u64);
+                self.column = (text.len() - last - 1) as u64;
+            }
+            None => self.column += text.len() as u64,
+        }
+    }
+}
+
+// Width reserved for each count in the header written by
+// `EventrecordWriter`, enough for any u64
+const COUNT_WIDTH: usize = 20;
+
+/// Incremental writer for event records
+///
+/// Events are written one at a time. The writer keeps track of the
+/// number of events, subevents, and reweighting entries and fills in
+/// the corresponding `<Eventrecord>` attributes when the record is
+/// [finished](Self::finish).
+pub struct EventrecordWriter<W> {
+    writer: W,
+    header: EventrecordHeader,
+    nevents: u64,
+    nsubevents: u64,
+    nreweights: u64,
+    options: WriteOptions,
+    patch_header: Option<(u64, PatchCounts<W>)>,
+    // the number of record-level extra children written so far
+    nextra: usize,
+}
+
+type PatchCounts<W> =
+    fn(&mut W, u64, &EventrecordHeader) -> std::io::Result<()>;
+
+impl<W: Write + Seek> EventrecordWriter<W> {
+    /// Start a new record
+    ///
+    /// The header counts are written as placeholders and patched in
+    /// [finish](Self::finish).
+    pub fn new(
+        writer: W,
+        name: impl Into<String>,
+        alpha_s_power: u64,
+    ) -> Result<Self, WriteErr> {
+        let header = EventrecordHeader {
+            alpha_s_power,
+            name: name.into(),
+            ..Default::default()
+        };
+        Self::from_header(writer, header)
+    }
+
+    /// Start a new record with the given header
+    ///
+    /// In contrast to [with_header](Self::with_header), the header
+    /// counts are ignored and patched in [finish](Self::finish). The
+    /// [extra](EventrecordHeader::extra) content is kept.
+    pub fn from_header(
+        mut writer: W,
+        header: EventrecordHeader,
+    ) -> Result<Self, WriteErr> {
+        let start = writer.stream_position()?;
+        header.write_start(&mut writer, COUNT_WIDTH)?;
+        let mut nextra = 0;
+        header.write_preamble(&mut writer, &mut nextra)?;
+        Ok(Self {
+            writer,
+            header,
+            nevents: 0,
+            nsubevents: 0,
+            nreweights: 0,
+            options: WriteOptions::default(),
+            patch_header: Some((start, patch_counts::<W>)),
+            nextra,
+        })
+    }
+}
+
+fn patch_counts<W: Write + Seek>(
+    writer: &mut W,
+    start: u64,
+    header: &EventrecordHeader,
+) -> std::io::Result<()> {
+    use std::io::SeekFrom;
+
+    let end = writer.stream_position()?;
+    writer.seek(SeekFrom::Start(start + b"<Eventrecord ".len() as u64))?;
+    header.write_counts(writer, COUNT_WIDTH)?;
+    writer.seek(SeekFrom::Start(end))?;
+    Ok(())
+}
+
+impl<W: Write> EventrecordWriter<W> {
+    /// Start a new record on a writer that does not support seeking
+    ///
+    /// The header is written as given. [finish](Self::finish) checks
+    /// that the declared counts agree with the events actually written.
+    pub fn with_header(
+        mut writer: W,
+        header: EventrecordHeader,
+    ) -> Result<Self, WriteErr> {
+        header.write_start(&mut writer, 0)?;
+        let mut nextra = 0;
+        header.write_preamble(&mut writer, &mut nextra)?;
+        Ok(Self {
+            writer,
+            header,
+            nevents: 0,
+            nsubevents: 0,
+            nreweights: 0,
+            options: WriteOptions::default(),
+            patch_header: None,
+            nextra,
+        })
+    }
+
+    /// The number of events written so far
+    pub fn nevents(&self) -> u64 {
+        self.nevents
+    }
+
+    /// The number of subevents written so far
+    pub fn nsubevents(&self) -> u64 {
+        self.nsubevents
+    }
+
+    /// The number of reweighting entries written so far
+    pub fn nreweights(&self) -> u64 {
+        self.nreweights
+    }
+
+    /// Set the options for writing events
+    pub fn write_options(mut self, options: WriteOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Add record-level content that was read after the writer was created
+    ///
+    /// `extra` replaces the [extra](EventrecordHeader::extra) content
+    /// of the header. Comments and elements are written before the
+    /// event at their [position](ExtraChild::position), or before the
+    /// end of the record. Attributes and children that have already
+    /// been written are not changed. This is intended for copying a
+    /// record read in [lossless](EventrecordReader::lossless) mode,
+    /// passing the header extra content before writing each event and
+    /// before finishing the record.
+    pub fn update_extra(&mut self, extra: &ExtraContent) {
+        let written = &self.header.extra.children[..self.nextra];
+        let new = extra.children.get(self.nextra..).unwrap_or_default();
+        let children = written.iter().chain(new).cloned().collect();
+        self.header.extra.children = children;
+    }
+
+    /// Write the next event
+    pub fn write_event(&mut self, event: &Event) -> Result<(), WriteErr> {
+        let position = self.nevents as usize;
+        let extra = &self.header.extra;
+        extra.write_children_before(
+            &mut self.writer,
+            &mut self.nextra,
+            position,
+        )?;
+        event.write_with(&mut self.writer, &self.options)?;
+        self.nevents += 1;
+        self.nsubevents += event.subevents.len() as u64;
+        self.nreweights += count_reweights(event);
+        Ok(())
+    }
+
+    /// Close the record and return the underlying writer
+    pub fn finish(mut self) -> Result<W, WriteErr> {
+        let extra = &self.header.extra;
+        extra.write_children_before(
+            &mut self.writer,
+            &mut self.nextra,
+            usize::MAX,
+        )?;
+        self.writer.write_all(b"</Eventrecord>\n")?;
+        let actual = [
+            ("nevents", self.nevents),
+            ("nsubevents", self.nsubevents),
+            ("nreweights", self.nreweights),
+        ];
+        if let Some((start, patch_counts)) = self.patch_header {
+            self.header.nevents = self.nevents;
+            self.header.nsubevents = self.nsubevents;
+            self.header.nreweights = self.nreweights;
+            patch_counts(&mut self.writer, start, &self.header)?;
+        } else {
+            let declared = [
+                self.header.nevents,
+                self.header.nsubevents,
+                self.header.nreweights,
+            ];
+            for ((attribute, actual), declared) in
+                actual.into_iter().zip(declared)
+            {
+                if actual != declared {
+                    return Err(WriteErr::CountMismatch {
+                        attribute,
+                        declared,
+                        actual,
+                    });
+                }
+            }
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Rewrite a possibly truncated record
+///
+/// All complete events in `input` are copied to `output`, followed by
+/// the closing `</Eventrecord>` tag. The header counts are set to the
+/// number of copied events, subevents, and reweighting entries. The
+/// input is read in [lossless](EventrecordReader::lossless) mode, so
+/// comments and unknown attributes and elements are kept.
+/// Returns the header of the repaired record.
+pub fn repair<R: BufRead, W: Write + Seek>(
+    input: R,
+    output: W,
+) -> Result<EventrecordHeader, RepairErr> {
+    let mut reader = EventrecordReader::new(input)?
+        .lossless(true)
+        .allow_truncated(true);
+    let mut writer =
+        EventrecordWriter::from_header(output, reader.header().clone())?;
+    while let Some(event) = reader.next() {
+        let event = event?;
+        writer.update_extra(&reader.header().extra);
+        writer.write_event(&event)?;
+    }
+    writer.update_extra(&reader.header().extra);
+    let mut header = reader.header().clone();
+    header.nevents = writer.nevents();
+    header.nsubevents = writer.nsubevents();
+    header.nreweights = writer.nreweights();
+    writer.finish()?;
+    Ok(header)
+}
+
+fn count_reweights(event: &Event) -> u64 {
+    event
+        .subevents
+        .iter()
+        .map(|s| s.reweight.len() as u64)
+        .sum()
+}
+
+pub(crate) fn write_generator_comment<W: Write>(
+    writer: &mut W,
+) -> std::io::Result<()> {
+    writeln!(
+        writer,
+        "<!--\nRecord generated with {} {}\n-->",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+#[derive(Debug, Error)]
+pub enum WriteErr {
+    #[error("Failed to write record")]
+    Io(#[from] std::io::Error),
+    #[error("Header declares {declared} for `{attribute}`, but {actual} were written")]
+    CountMismatch {
+        attribute: &'static str,
+        declared: u64,
+        actual: u64,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum RepairErr {
+    #[error("Failed to read record")]
+    Read(#[from] ReadErr),
+    #[error("Failed to write record")]
+    Write(#[from] WriteErr),
+}
+
+#[derive(Debug, Error)]
+pub enum ReadErr {
+    #[error("Failed to read input")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to read XML")]
+    Xml(#[from] quick_xml::Error),
+    #[error("Failed to parse record")]
+    Parse(#[from] LocatedParseErr),
+    #[error("No <Eventrecord> element found")]
+    NoEventrecord,
+    #[error("<Eventrecord> is missing the attribute `{0}`")]
+    MissingAttribute(&'static str),
+    #[error("Invalid value '{1}' for the attribute `{0}` of <Eventrecord>")]
+    InvalidAttribute(&'static str, String),
+    #[error("Unexpected end of input")]
+    UnexpectedEof,
+    #[error("Event {0} is not in the index")]
+    NoSuchEvent(usize),
+    #[error("The event index does not match the record")]
+    StaleIndex,
+}
+
 #[derive(Debug, Error)]
 pub enum ParseErr {
     #[error("'{0}' is not a comma-separated list with {1} float values")]
     NumEntries(String, usize),
+    #[error("'{0}' is not a finite floating-point number")]
+    InvalidFloat(String),
+    #[error("'{0}' is not a valid particle status")]
+    InvalidStatus(String),
+    #[error("'{0}' is not a valid PDG particle id")]
+    InvalidPdgCode(String),
+    #[error("'{0}' is not a valid number of events")]
+    InvalidCount(String),
+    #[error("'{0}' is not a valid channel number")]
+    InvalidChannel(String),
+    #[error("'{0}' is not a valid channel definition")]
+    InvalidChannelDef(String),
+    #[error("Channel {channel} declares {declared} parton pairs, but lists {actual}")]
+    NumPairs {
+        channel: u32,
+        declared: usize,
+        actual: usize,
+    },
+    #[error("Expected channel {expected}, found channel {found}")]
+    NonContiguousChannel { expected: u32, found: u32 },
+    #[error(
+        "'{0}' is not of the form '<beam> <beam> with <PDF set>/<member>'"
+    )]
+    InvalidIncoming(String),
+    #[error("'{0}' is not a known beam particle")]
+    InvalidBeam(String),
+    #[error("'{0}' is not a valid scale definition")]
+    InvalidScale(String),
+    #[error("'{0}' is not a known format version")]
+    InvalidVersion(String),
+    #[error("Missing attribute `{0}`")]
+    MissingAttribute(&'static str),
+    #[error("Expected {0}, found '{1}'")]
+    Unexpected(&'static str, String),
+    #[error(
+        "Header declares {declared} for `{attribute}`, but found {actual}"
+    )]
+    HeaderMismatch {
+        attribute: &'static str,
+        declared: u64,
+        actual: u64,
+    },
+}
+
+/// A [ParseErr] together with the place where it occurred
+#[derive(Debug, Error)]
+#[error("Parse error at {location}")]
+pub struct LocatedParseErr {
+    pub location: Location,
+    #[source]
+    pub kind: ParseErr,
+}
+
+impl LocatedParseErr {
+    // translate the location from a part of the input to the whole input
+    pub(crate) fn offset_by(mut self, start: &Location) -> Self {
+        self.location = self.location.offset_by(start);
+        self
+    }
+}
+
+/// Position in the input
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Location {
+    /// Index of the event in the record, starting from zero
+    pub event: Option<u64>,
+    /// Index of the subevent in the event, starting from zero
+    pub subevent: Option<usize>,
+    /// Byte offset
+    pub offset: u64,
+    /// Line number, starting from one
+    ///
+    /// This is unknown after seeking in the input.
+    pub line: Option<u64>,
+    /// Column in bytes, starting from one
+    pub column: Option<u64>,
+}
+
+impl Location {
+    pub(crate) fn in_str(input: &str, offset: usize) -> Self {
+        let mut pos = LinePosition::START;
+        pos.advance(&input.as_bytes()[..offset]);
+        Self {
+            offset: offset as u64,
+            line: pos.line,
+            column: Some(pos.column + 1),
+            ..Default::default()
+        }
+    }
+
+    // translate a location relative to `start` to an absolute location
+    pub(crate) fn offset_by(self, start: &Location) -> Self {
+        let column = if self.line == Some(1) {
+            start.column.zip(self.column).map(|(s, c)| s + c - 1)
+        } else {
+            self.column
+        };
+        Self {
+            event: self.event.or(start.event),
+            subevent: self.subevent.or(start.subevent),
+            offset: start.offset + self.offset,
+            line: start.line.zip(self.line).map(|(s, l)| s + l - 1),
+            column,
+        }
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(event) = self.event {
+            write!(f, "event {event}, ")?;
+        }
+        if let Some(subevent) = self.subevent {
+            write!(f, "subevent {subevent}, ")?;
+        }
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, "line {line}, column {column}, ")?;
+        }
+        write!(f, "byte {}", self.offset)
+    }
+}
+
+// parse a finite floating-point number
+pub(crate) fn parse_float(s: &str) -> Result<f64, ParseErr> {
+    match s.trim().parse::<f64>() {
+        Ok(x) if x.is_finite() => Ok(x),
+        _ => Err(ParseErr::InvalidFloat(s.to_owned())),
+    }
 }
 
 #[cfg(test)]
@@ -450,4 +1879,495 @@ File generated with STRIPPER v0.1 for online data base
             quick_xml::de::from_reader(tmp.as_slice()).unwrap();
         assert_eq!(record, record_2);
     }
+
+    #[test]
+    fn ser_file_significant_digits() {
+        use crate::format::FloatFormat;
+
+        let record: Eventrecord = quick_xml::de::from_str(REF_RECORD).unwrap();
+        let options = WriteOptions::default()
+            .float_format(FloatFormat::Significant(10))
+            .tolerance(1e-9);
+        let mut out = Vec::new();
+        record.write_with(&mut out, &options).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(
+            r#"<se w="-2.098171554e-05" muR="1140.717994" muF="1140.717994">"#
+        ));
+        assert!(out.contains(
+            r#"<p id="0,6">3334.580936,-386.757619,943.5205498,-3170.151619</p>"#
+        ));
+        assert!(out.contains(
+            r#"<rw ch="12">0.8893243414,0.05144448245,-0.0002369763508</rw>"#
+        ));
+        let record_2: Eventrecord = quick_xml::de::from_str(&out).unwrap();
+        assert_eq!(record, record_2);
+
+        let options =
+            WriteOptions::default().float_format(FloatFormat::Significant(3));
+        let mut out = Vec::new();
+        record.write_with(&mut out, &options).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(r#"<p id="1,21">5.78e+03,0,0,5.78e+03</p>"#));
+    }
+
+    #[test]
+    fn parser_agrees_with_serde() {
+        let record: Eventrecord = quick_xml::de::from_str(REF_RECORD).unwrap();
+        let mut rest = REF_RECORD;
+        let mut events = Vec::new();
+        while let Some(start) = rest.find("<e>") {
+            let end = rest.find("</e>").unwrap() + "</e>".len();
+            events.push(crate::parser::parse_event(&rest[start..end]).unwrap());
+            rest = &rest[end..];
+        }
+        assert_eq!(events, record.events);
+    }
+
+    #[test]
+    fn read_events() {
+        let record: Eventrecord = quick_xml::de::from_str(REF_RECORD).unwrap();
+        let reader = EventrecordReader::new(REF_RECORD.as_bytes()).unwrap();
+        assert_eq!(
+            reader.header(),
+            &EventrecordHeader {
+                nevents: 2286,
+                nsubevents: 2286,
+                nreweights: 2286,
+                alpha_s_power: 2,
+                name: "Bm".to_owned(),
+                version: FormatVersion::V0_1,
+                extra: Default::default(),
+            }
+        );
+        let events: Vec<_> = reader.map(Result::unwrap).collect();
+        assert_eq!(events, record.events);
+    }
+
+    #[test]
+    fn detect_version() {
+        let record: Eventrecord = quick_xml::de::from_str(REF_RECORD).unwrap();
+        assert_eq!(record.version, None);
+        assert_eq!(record.header().version, FormatVersion::LATEST);
+        let detected = Eventrecord::from_reader(REF_RECORD.as_bytes()).unwrap();
+        assert_eq!(detected.version, Some(FormatVersion::V0_1));
+        assert_eq!(detected.events, record.events);
+
+        let start = REF_RECORD.find("<!--").unwrap();
+        let end = REF_RECORD.find("-->").unwrap() + "-->".len();
+        let no_comment =
+            format!("{}{}", &REF_RECORD[..start], &REF_RECORD[end..]);
+        let detected = Eventrecord::from_reader(no_comment.as_bytes()).unwrap();
+        assert_eq!(detected.version, Some(FormatVersion::LATEST));
+
+        // unknown versions are read like the latest one
+        let unknown = REF_RECORD.replace("STRIPPER v0.1", "STRIPPER v0.2");
+        let version = FormatVersion::Unknown("0.2".to_owned());
+        let reader = EventrecordReader::new(unknown.as_bytes()).unwrap();
+        assert_eq!(reader.header().version, version);
+        let header = EventrecordHeader::peek(unknown.as_bytes()).unwrap();
+        assert_eq!(header.version, version);
+        let detected = Eventrecord::from_reader(unknown.as_bytes()).unwrap();
+        assert_eq!(detected.version, Some(version));
+        assert_eq!(detected.events, record.events);
+    }
+
+    #[test]
+    fn peek_header() {
+        // fails on any attempt to read beyond the first event
+        struct Unreadable;
+
+        impl Read for Unreadable {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::Other.into())
+            }
+        }
+
+        let first_event = REF_RECORD.find("<e>").unwrap() + "<e>".len();
+        let start = &REF_RECORD.as_bytes()[..first_event];
+        let input = std::io::BufReader::new(start.chain(Unreadable));
+        let header = EventrecordHeader::peek(input).unwrap();
+        let record: Eventrecord = quick_xml::de::from_str(REF_RECORD).unwrap();
+        assert_eq!(
+            header,
+            EventrecordHeader {
+                extra: ExtraContent {
+                    children: vec![ExtraChild {
+                        position: 0,
+                        node: ExtraNode::Comment(
+                            "\nFile generated with STRIPPER v0.1 for online data base\n"
+                                .to_owned()
+                        ),
+                    }],
+                    ..Default::default()
+                },
+                ..record.header()
+            }
+        );
+        assert_eq!(header.version, FormatVersion::V0_1);
+
+        assert!(matches!(
+            EventrecordHeader::peek("<e/>".as_bytes()),
+            Err(ReadErr::NoEventrecord)
+        ));
+    }
+
+    #[test]
+    fn read_events_unexpected_eof() {
+        let end = REF_RECORD.find("</Eventrecord>").unwrap();
+        let reader =
+            EventrecordReader::new(&REF_RECORD.as_bytes()[..end]).unwrap();
+        let events: Vec<_> = reader.collect();
+        assert_eq!(events.len(), 5);
+        assert!(matches!(events[4], Err(ReadErr::UnexpectedEof)));
+    }
+
+    #[test]
+    fn read_events_check_counts() {
+        let reader = EventrecordReader::new(REF_RECORD.as_bytes())
+            .unwrap()
+            .check_counts(true);
+        let events: Vec<_> = reader.collect();
+        assert_eq!(events.len(), 5);
+        let Err(ReadErr::Parse(err)) = &events[4] else {
+            panic!("expected header mismatch");
+        };
+        assert!(matches!(
+            err.kind,
+            ParseErr::HeaderMismatch {
+                attribute: "nevents",
+                declared: 2286,
+                actual: 4
+            }
+        ));
+        assert_eq!(err.location.event, None);
+
+        let record = REF_RECORD.replace(
+            r#"nevents="2286" nsubevents="2286" nreweights="2286""#,
+            r#"nevents="4" nsubevents="4" nreweights="4""#,
+        );
+        let reader = EventrecordReader::new(record.as_bytes())
+            .unwrap()
+            .check_counts(true);
+        assert!(reader.collect::<Result<Vec<_>, _>>().is_ok());
+    }
+
+    #[test]
+    fn read_events_truncated() {
+        let record: Eventrecord = quick_xml::de::from_str(REF_RECORD).unwrap();
+        let end = REF_RECORD.find("</Eventrecord>").unwrap();
+        let mut reader = EventrecordReader::new(&REF_RECORD.as_bytes()[..end])
+            .unwrap()
+            .allow_truncated(true);
+        let events: Vec<_> = reader.by_ref().map(Result::unwrap).collect();
+        assert_eq!(events, record.events);
+        assert!(reader.is_truncated());
+
+        let last_event = REF_RECORD.rfind("<e>").unwrap();
+        for end in [
+            last_event + 2,
+            last_event + 10,
+            REF_RECORD.rfind("-2806.931212").unwrap(),
+            REF_RECORD.rfind("</se>").unwrap() + 2,
+        ] {
+            let mut reader =
+                EventrecordReader::new(&REF_RECORD.as_bytes()[..end])
+                    .unwrap()
+                    .allow_truncated(true);
+            let events: Vec<_> = reader.by_ref().map(Result::unwrap).collect();
+            assert_eq!(events, record.events[..3]);
+            assert!(reader.is_truncated());
+        }
+
+        let mut reader = EventrecordReader::new(REF_RECORD.as_bytes())
+            .unwrap()
+            .allow_truncated(true);
+        assert_eq!(reader.by_ref().count(), 4);
+        assert!(!reader.is_truncated());
+    }
+
+    #[test]
+    fn repair_truncated() {
+        let record: Eventrecord = quick_xml::de::from_str(REF_RECORD).unwrap();
+        let end = REF_RECORD.rfind("</se>").unwrap();
+        let mut out = std::io::Cursor::new(Vec::new());
+        let header = repair(&REF_RECORD.as_bytes()[..end], &mut out).unwrap();
+        assert_eq!(header.nevents, 3);
+        assert_eq!(header.nsubevents, 3);
+        assert_eq!(header.nreweights, 3);
+        let peeked = EventrecordHeader::peek(out.get_ref().as_slice());
+        assert_eq!(peeked.unwrap(), header);
+        let repaired: Eventrecord =
+            quick_xml::de::from_reader(out.get_ref().as_slice()).unwrap();
+        assert_eq!(repaired.events, record.events[..3]);
+    }
+
+    #[test]
+    fn repair_keeps_extra() {
+        let input = REF_RECORD
+            .replacen("name=\"Bm\"", "name=\"Bm\" job=\"17\"", 1)
+            .replacen("<e>\n<se", "<e>\n<!-- first event -->\n<se", 1);
+        let end = input.rfind("</se>").unwrap();
+        let mut out = std::io::Cursor::new(Vec::new());
+        let header = repair(&input.as_bytes()[..end], &mut out).unwrap();
+        assert_eq!(header.nevents, 3);
+        let out = String::from_utf8(out.into_inner()).unwrap();
+        assert!(out.contains(
+            "<!--\nFile generated with STRIPPER v0.1 for online data base\n-->"
+        ));
+        assert!(!out.contains("Record generated with"));
+        assert!(out.contains(" job=\"17\">"));
+        assert!(out.contains("<!-- first event -->"));
+        let repaired = EventrecordHeader::peek(out.as_bytes()).unwrap();
+        assert_eq!(repaired, header);
+    }
+
+    #[test]
+    fn read_events_lenient() {
+        let record: Eventrecord = quick_xml::de::from_str(REF_RECORD).unwrap();
+        let broken = REF_RECORD
+            .replace("5780.608219,0,0,5780.608219", "nan,0,0,5780.608219")
+            .replace(
+                "0.2477027669,0.6207262768,-2.098171554e-05",
+                "0.2477027669",
+            );
+        let reader = EventrecordReader::new(broken.as_bytes()).unwrap();
+        let events: Vec<_> = reader.collect();
+        let Err(ReadErr::Parse(err)) = &events[0] else {
+            panic!("expected parse error");
+        };
+        assert!(matches!(err.kind, ParseErr::InvalidFloat(_)));
+        assert_eq!(err.location.event, Some(0));
+        assert_eq!(err.location.subevent, Some(0));
+        assert_eq!(err.location.line, Some(8));
+        assert_eq!(err.location.column, Some(14));
+        assert!(broken[err.location.offset as usize..].starts_with(" nan,"));
+
+        let mut reader = EventrecordReader::new(broken.as_bytes())
+            .unwrap()
+            .lenient(true);
+        let events: Vec<_> = reader.by_ref().map(Result::unwrap).collect();
+        assert_eq!(
+            events,
+            [record.events[1].clone(), record.events[3].clone()]
+        );
+        let diagnostics = reader.diagnostics();
+        assert_eq!(diagnostics.nread(), 2);
+        assert_eq!(diagnostics.nskipped(), 2);
+        assert_eq!(diagnostics.ntotal(), 4);
+        let skipped = diagnostics.skipped();
+        assert_eq!(skipped[0].index, 0);
+        assert_eq!(skipped[1].index, 2);
+        for skipped in skipped {
+            assert!(broken[skipped.position as usize..].starts_with("<e>"));
+        }
+        assert!(matches!(skipped[0].err.kind, ParseErr::InvalidFloat(_)));
+        assert!(matches!(skipped[1].err.kind, ParseErr::NumEntries(_, 2)));
+        assert_eq!(skipped[1].err.location.event, Some(2));
+        assert_eq!(skipped[1].err.location.subevent, Some(0));
+    }
+
+    #[test]
+    fn write_events() {
+        let record: Eventrecord = quick_xml::de::from_str(REF_RECORD).unwrap();
+        let out = std::io::Cursor::new(Vec::new());
+        let mut writer = EventrecordWriter::new(out, "Bm", 2).unwrap();
+        for event in &record.events {
+            writer.write_event(event).unwrap();
+        }
+        let out = writer.finish().unwrap().into_inner();
+        let record_2: Eventrecord =
+            quick_xml::de::from_reader(out.as_slice()).unwrap();
+        assert_eq!(record_2.nevents, 4);
+        assert_eq!(record_2.nsubevents, 4);
+        assert_eq!(record_2.nreweights, 4);
+        assert_eq!(record_2.alpha_s_power, 2);
+        assert_eq!(record_2.name, "Bm");
+        assert_eq!(record_2.events, record.events);
+    }
+
+    #[test]
+    fn read_write_lossless() {
+        let record = REF_RECORD
+            .replacen("2286", "4", 3)
+            .replacen(r#"name="Bm">"#, r#"name="Bm" run="7">"#, 1)
+            .replacen("<e>", "<meta><seed> 42 </seed></meta>\n<e>", 1)
+            .replacen(
+                r#"muF="91.16253934">"#,
+                r#"muF="91.16253934" id="a&amp;b">
+<!-- first subevent --><extra/>"#,
+                1,
+            );
+        let read = |lossless| {
+            let reader = EventrecordReader::new(record.as_bytes()).unwrap();
+            let reader = reader.lossless(lossless);
+            let header = reader.header().clone();
+            let events: Vec<_> = reader.map(Result::unwrap).collect();
+            (header, events)
+        };
+
+        let (header, events) = read(false);
+        assert!(header.extra.is_empty());
+        assert!(events[0].extra().is_empty());
+
+        let (header, events) = read(true);
+        let extra = &header.extra;
+        assert_eq!(extra.attributes, [("run".to_owned(), "7".to_owned())]);
+        let comments: Vec<_> = extra.comments().collect();
+        assert_eq!(
+            comments,
+            ["\nFile generated with STRIPPER v0.1 for online data base\n"]
+        );
+        let elements: Vec<_> = extra.elements().collect();
+        assert_eq!(elements, ["<meta><seed> 42 </seed></meta>"]);
+        let extra = events[0].extra().subevent(0);
+        assert_eq!(extra.attributes, [("id".to_owned(), "a&amp;b".to_owned())]);
+        assert_eq!(
+            extra.children,
+            [
+                ExtraChild {
+                    position: 0,
+                    node: ExtraNode::Comment(" first subevent ".to_owned())
+                },
+                ExtraChild {
+                    position: 0,
+                    node: ExtraNode::Element("<extra/>".to_owned())
+                },
+            ]
+        );
+
+        let mut writer =
+            EventrecordWriter::with_header(Vec::new(), header.clone()).unwrap();
+        for event in &events {
+            writer.write_event(event).unwrap();
+        }
+        let out = writer.finish().unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("STRIPPER v0.1"));
+        assert!(!out.contains(env!("CARGO_PKG_NAME")));
+        let mut reader = EventrecordReader::new(out.as_bytes())
+            .unwrap()
+            .lossless(true);
+        assert_eq!(reader.header(), &header);
+        let events_2: Vec<_> = reader.by_ref().map(Result::unwrap).collect();
+        assert_eq!(events_2, events);
+    }
+
+    #[test]
+    fn lossless_round_trip() {
+        const RECORD: &str = r#"<Eventrecord nevents="3" nsubevents="2" nreweights="2" as="2" name="Bm" run="7">
+<!--
+File generated with STRIPPER v0.1 for online data base
+-->
+<meta><seed> 42 </seed></meta>
+<!-- before the first event -->
+<e id="1">
+<!-- before the first subevent -->
+<se w="-0.0002369763508" muR="91.16253934" muF="91.16253934" id="a&amp;b">
+<!-- before the first particle -->
+<p id="1,21">5780.608219,0,0,5780.608219</p>
+<extra><!-- inside --></extra>
+<p id="1,21">334.3891359,0,0,-334.3891359</p>
+<!-- between particles and reweights -->
+<rw ch="12">0.8893243414,0.05144448245,-0.0002369763508</rw>
+<!-- after the last reweight -->
+<x/>
+</se>
+<between/>
+<se w="1" muR="2" muF="3">
+<rw ch="1">0.1,0.2,3</rw>
+</se>
+<!-- after the last subevent -->
+</e>
+<!-- between events -->
+<other a="1"><!-- nested --></other>
+<e>
+</e>
+<!-- before an empty event -->
+<e note="empty">
+</e>
+<!-- after the last event -->
+<trailer/>
+</Eventrecord>
+"#;
+        let mut reader = EventrecordReader::new(RECORD.as_bytes())
+            .unwrap()
+            .lossless(true);
+        let mut writer =
+            EventrecordWriter::with_header(Vec::new(), reader.header().clone())
+                .unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = reader.next() {
+            let event = event.unwrap();
+            writer.update_extra(&reader.header().extra);
+            writer.write_event(&event).unwrap();
+            events.push(event);
+        }
+        writer.update_extra(&reader.header().extra);
+        let out = writer.finish().unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), RECORD);
+
+        let record = Eventrecord {
+            events,
+            ..Default::default()
+        };
+        let header = reader.header().clone();
+        let mut record = Eventrecord {
+            nevents: header.nevents,
+            nsubevents: header.nsubevents,
+            nreweights: header.nreweights,
+            alpha_s_power: header.alpha_s_power,
+            name: header.name,
+            version: Some(header.version),
+            ..record
+        };
+        *record.extra_mut() = header.extra;
+        let mut out = Vec::new();
+        record.write(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), RECORD);
+
+        let empty =
+            RECORD.replace("<e note=\"empty\">\n</e>", "<e note=\"empty\"/>");
+        let reader = EventrecordReader::new(empty.as_bytes())
+            .unwrap()
+            .lossless(true);
+        let events: Vec<_> = reader.map(Result::unwrap).collect();
+        assert_eq!(events[2], record.events[2]);
+    }
+
+    #[test]
+    fn write_events_unseekable() {
+        let record: Eventrecord = quick_xml::de::from_str(REF_RECORD).unwrap();
+        let header = EventrecordHeader {
+            nevents: 4,
+            nsubevents: 4,
+            nreweights: 4,
+            ..record.header()
+        };
+        let mut writer =
+            EventrecordWriter::with_header(Vec::new(), header).unwrap();
+        for event in &record.events {
+            writer.write_event(event).unwrap();
+        }
+        let out = writer.finish().unwrap();
+        let record_2: Eventrecord =
+            quick_xml::de::from_reader(out.as_slice()).unwrap();
+        assert_eq!(record_2.header().nevents, 4);
+        assert_eq!(record_2.events, record.events);
+
+        let mut writer =
+            EventrecordWriter::with_header(Vec::new(), record.header())
+                .unwrap();
+        for event in &record.events {
+            writer.write_event(event).unwrap();
+        }
+        assert!(matches!(
+            writer.finish(),
+            Err(WriteErr::CountMismatch {
+                attribute: "nevents",
+                declared: 2286,
+                actual: 4
+            })
+        ));
+    }
 }
@@ -0,0 +1,225 @@
+//! Output formatting options
+//!
+//! [WriteOptions] control how floating-point numbers are written by
+//! [WriteXML](crate::WriteXML). The default is the shortest
+//! representation that reads back to the same value. STRIPPER itself
+//! writes numbers with ten significant digits, which corresponds to
+//! [FloatFormat::Significant(10)](FloatFormat::Significant).
+use std::fmt::Write;
+
+/// Options for writing XML output
+#[derive(Copy, Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct WriteOptions {
+    /// The format of floating-point numbers
+    pub float_format: FloatFormat,
+    /// Warn when a written number differs by more than this relative
+    /// tolerance from the original value
+    ///
+    /// Warnings are emitted with [log::warn].
+    pub tolerance: Option<f64>,
+}
+
+impl WriteOptions {
+    /// Use the given format for floating-point numbers
+    pub fn float_format(mut self, float_format: FloatFormat) -> Self {
+        self.float_format = float_format;
+        self
+    }
+
+    /// Warn about numbers that change by more than the given relative amount
+    pub fn tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = Some(tolerance);
+        self
+    }
+
+    // write a floating-point number
+    pub(crate) fn write_float(&self, out: &mut String, x: f64) {
+        let start = out.len();
+        self.float_format.write(out, x);
+        let Some(tolerance) = self.tolerance else {
+            return;
+        };
+        if self.float_format == FloatFormat::Shortest || !x.is_finite() {
+            return;
+        }
+        let written = &out[start..];
+        let y: f64 = written.parse().unwrap();
+        let diff = (y - x).abs();
+        if diff > tolerance * x.abs() {
+            log::warn!(
+                "{x} written as {written}, relative change {} exceeds tolerance {tolerance}",
+                diff / x.abs()
+            );
+        }
+    }
+
+    // write a comma-separated list of floating-point numbers
+    pub(crate) fn write_floats(
+        &self,
+        out: &mut String,
+        xs: impl IntoIterator<Item = f64>,
+    ) {
+        for (n, x) in xs.into_iter().enumerate() {
+            if n > 0 {
+                out.push(',');
+            }
+            self.write_float(out, x);
+        }
+    }
+}
+
+/// Format of floating-point numbers
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum FloatFormat {
+    /// Shortest representation that reads back to the same value
+    #[default]
+    Shortest,
+    /// The given number of significant digits
+    ///
+    /// This is the same as the `%.Ng` format of C's `printf`: numbers
+    /// with very large or small exponents are written in scientific
+    /// notation and trailing zeros are removed.
+    Significant(usize),
+    /// Scientific notation with the given number of significant digits
+    ///
+    /// This is the same as the `%.Ne` format of C's `printf`, with
+    /// `N` one less than the number of digits.
+    Scientific(usize),
+}
+
+impl FloatFormat {
+    fn write(self, out: &mut String, x: f64) {
+        if !x.is_finite() {
+            // `NaN`, `inf`, or `-inf`
+            write!(out, "{x}").unwrap();
+            return;
+        }
+        match self {
+            Self::Shortest => write!(out, "{x}").unwrap(),
+            Self::Significant(digits) => write_significant(out, x, digits),
+            Self::Scientific(digits) => {
+                let (mantissa, exp) = split_exp(x, digits);
+                write_scientific(out, &mantissa, exp);
+            }
+        }
+    }
+}
+
+// format as `%.{digits}g`
+fn write_significant(out: &mut String, x: f64, digits: usize) {
+    let digits = digits.max(1);
+    if x == 0. {
+        out.push_str(if x.is_sign_negative() { "-0" } else { "0" });
+        return;
+    }
+    let (mantissa, exp) = split_exp(x, digits);
+    if exp < -4 || exp >= digits as i32 {
+        write_scientific(out, trim_zeros(&mantissa), exp);
+    } else {
+        let decimals = (digits as i32 - 1 - exp) as usize;
+        let fixed = format!("{x:.decimals$}");
+        out.push_str(trim_zeros(&fixed));
+    }
+}
+
+// the mantissa and exponent in scientific notation with the given
+// number of significant digits
+fn split_exp(x: f64, digits: usize) -> (String, i32) {
+    let decimals = digits.max(1) - 1;
+    let formatted = format!("{x:.decimals$e}");
+    let (mantissa, exp) = formatted.split_once('e').unwrap();
+    (mantissa.to_owned(), exp.parse().unwrap())
+}
+
+// write in C style, with a sign and at least two digits in the exponent
+fn write_scientific(out: &mut String, mantissa: &str, exp: i32) {
+    let sign = if exp < 0 { '-' } else { '+' };
+    write!(out, "{mantissa}e{sign}{:02}", exp.abs()).unwrap();
+}
+
+fn trim_zeros(num: &str) -> &str {
+    if num.contains('.') {
+        num.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        num
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(x: f64, format: FloatFormat) -> String {
+        let mut out = String::new();
+        format.write(&mut out, x);
+        out
+    }
+
+    #[test]
+    fn significant() {
+        let stripper = FloatFormat::Significant(10);
+        for (x, expected) in [
+            (5780.608219, "5780.608219"),
+            (-0.0004385904665, "-0.0004385904665"),
+            (-2.098171554e-05, "-2.098171554e-05"),
+            (0.8893243414, "0.8893243414"),
+            (1e10, "1e+10"),
+            (488338734., "488338734"),
+            (0., "0"),
+            (1.0 / 3.0, "0.3333333333"),
+        ] {
+            assert_eq!(format(x, stripper), expected);
+        }
+        assert_eq!(format(9.99999, FloatFormat::Significant(3)), "10");
+        assert_eq!(format(999999., FloatFormat::Significant(3)), "1e+06");
+    }
+
+    #[test]
+    fn scientific() {
+        let sci = FloatFormat::Scientific(4);
+        assert_eq!(format(5780.608219, sci), "5.781e+03");
+        assert_eq!(format(-2.098171554e-05, sci), "-2.098e-05");
+        assert_eq!(format(0., sci), "0.000e+00");
+    }
+
+    #[test]
+    fn non_finite() {
+        use crate::{SubEvent, WriteXML};
+
+        for float_format in [
+            FloatFormat::Shortest,
+            FloatFormat::Significant(10),
+            FloatFormat::Scientific(4),
+        ] {
+            for (x, expected) in [
+                (f64::NAN, "NaN"),
+                (f64::INFINITY, "inf"),
+                (-f64::INFINITY, "-inf"),
+            ] {
+                assert_eq!(format(x, float_format), expected);
+                let options = WriteOptions::default()
+                    .float_format(float_format)
+                    .tolerance(0.);
+                let mut out = String::new();
+                options.write_float(&mut out, x);
+                assert_eq!(out, expected);
+
+                let subevent = SubEvent {
+                    weight: x,
+                    ..Default::default()
+                };
+                let mut out = Vec::new();
+                subevent.write_with(&mut out, &options).unwrap();
+                let out = String::from_utf8(out).unwrap();
+                assert!(out.contains(&format!("w=\"{expected}\"")));
+            }
+        }
+    }
+
+    #[test]
+    fn shortest() {
+        let x = 0.1 + 0.2;
+        let formatted = format(x, FloatFormat::Shortest);
+        assert_eq!(formatted.parse::<f64>().unwrap(), x);
+    }
+}
@@ -0,0 +1,426 @@
+//! Random access to the events in a record
+//!
+//! An [EventIndex] stores the byte offset of each `<e>` element in an
+//! uncompressed record file. It can be saved to a small sidecar file
+//! and used to jump straight to any event with
+//! [EventrecordReader::seek_event].
+//!
+//! To detect stale indices, the index also stores the size of the
+//! record and the counts declared in its header. They are checked
+//! when [loading](EventIndex::load) the index and when seeking.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, Write};
+use std::iter::Take;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use quick_xml::events::Event as XmlEvent;
+use quick_xml::name::QName;
+
+use crate::{EventrecordHeader, EventrecordReader, ReadErr};
+
+const MAGIC: &[u8] = b"STRIPIDX";
+const FORMAT_VERSION: u8 = 1;
+
+/// Byte offsets of the events in a record
+#[derive(Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct EventIndex {
+    offsets: Vec<u64>,
+    end: u64,
+    record_len: u64,
+    counts: [u64; 3],
+}
+
+impl EventIndex {
+    /// Build the index by scanning a record from its beginning
+    pub fn build<R: BufRead>(reader: R) -> Result<Self, ReadErr> {
+        let mut reader = quick_xml::Reader::from_reader(reader);
+        let mut buf = Vec::new();
+        let mut skip_buf = Vec::new();
+        let header = EventrecordHeader::read(&mut reader, &mut buf)?;
+        let counts = header_counts(&header);
+        let mut offsets = Vec::new();
+        let mut end = None;
+        loop {
+            buf.clear();
+            match reader.read_event_into(&mut buf)? {
+                XmlEvent::Start(start)
+                    if start.local_name().as_ref() == b"e" =>
+                {
+                    let len = start.len() + "<>".len();
+                    offsets.push((reader.buffer_position() - len) as u64);
+                    reader.read_to_end_into(QName(b"e"), &mut skip_buf)?;
+                }
+                XmlEvent::Empty(start)
+                    if start.local_name().as_ref() == b"e" =>
+                {
+                    let len = start.len() + "</>".len();
+                    offsets.push((reader.buffer_position() - len) as u64);
+                }
+                XmlEvent::End(end_tag)
+                    if end_tag.local_name().as_ref() == b"Eventrecord" =>
+                {
+                    let len = end_tag.len() + "</>".len();
+                    end = Some((reader.buffer_position() - len) as u64);
+                }
+                XmlEvent::Eof => {
+                    let end = end.ok_or(ReadErr::UnexpectedEof)?;
+                    return Ok(Self {
+                        offsets,
+                        end,
+                        record_len: reader.buffer_position() as u64,
+                        counts,
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The number of indexed events
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Whether the record contains no events
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// The byte offset of the `n`th event
+    pub fn offset(&self, n: usize) -> Option<u64> {
+        self.offsets.get(n).copied()
+    }
+
+    /// The byte offsets of all events
+    pub fn offsets(&self) -> &[u64] {
+        &self.offsets
+    }
+
+    /// The byte offset of the closing `</Eventrecord>` tag
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    /// The size of the indexed record in bytes
+    pub fn record_len(&self) -> u64 {
+        self.record_len
+    }
+
+    /// Check that the index belongs to a record with the given header
+    ///
+    /// The number of events, subevents, and reweighting entries
+    /// declared in the header has to match the indexed record.
+    pub fn check_header(
+        &self,
+        header: &EventrecordHeader,
+    ) -> Result<(), ReadErr> {
+        if header_counts(header) != self.counts {
+            return Err(ReadErr::StaleIndex);
+        }
+        Ok(())
+    }
+
+    /// Check that the index belongs to the given record file
+    ///
+    /// Both the size of the file and the counts declared in its header
+    /// have to match the indexed record.
+    pub fn check_record(
+        &self,
+        record: impl AsRef<Path>,
+    ) -> Result<(), ReadErr> {
+        let file = File::open(record)?;
+        if file.metadata()?.len() != self.record_len {
+            return Err(ReadErr::StaleIndex);
+        }
+        let mut reader = quick_xml::Reader::from_reader(BufReader::new(file));
+        let header = EventrecordHeader::read(&mut reader, &mut Vec::new())?;
+        self.check_header(&header)
+    }
+
+    /// The default sidecar file for a record file
+    ///
+    /// This is the record path with `.idx` appended.
+    pub fn sidecar_path(record: impl AsRef<Path>) -> PathBuf {
+        let mut path = record.as_ref().as_os_str().to_owned();
+        path.push(".idx");
+        path.into()
+    }
+
+    /// Save the index to a file
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Load the index for the given record file
+    ///
+    /// Returns [ReadErr::StaleIndex] if the index was built for a
+    /// different version of the record, see
+    /// [check_record](Self::check_record).
+    pub fn load(
+        path: impl AsRef<Path>,
+        record: impl AsRef<Path>,
+    ) -> Result<Self, ReadErr> {
+        let index = Self::read(BufReader::new(File::open(path)?))?;
+        index.check_record(record)?;
+        Ok(index)
+    }
+
+    /// Write the index in binary format
+    ///
+    /// Offsets are stored as variable-length differences to keep the
+    /// output small.
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[FORMAT_VERSION])?;
+        write_varint(&mut writer, self.record_len)?;
+        for count in self.counts {
+            write_varint(&mut writer, count)?;
+        }
+        write_varint(&mut writer, self.offsets.len() as u64)?;
+        let mut last = 0;
+        for &offset in self.offsets.iter().chain([&self.end]) {
+            write_varint(&mut writer, offset - last)?;
+            last = offset;
+        }
+        Ok(())
+    }
+
+    /// Read an index in the format produced by [write](Self::write)
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; MAGIC.len() + 1];
+        reader.read_exact(&mut magic)?;
+        if magic[..MAGIC.len()] != *MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an event index",
+            ));
+        }
+        if magic[MAGIC.len()] != FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unsupported event index version",
+            ));
+        }
+        let record_len = read_varint(&mut reader)?;
+        let mut counts = [0; 3];
+        for count in &mut counts {
+            *count = read_varint(&mut reader)?;
+        }
+        let len = read_varint(&mut reader)?;
+        let mut offsets = Vec::new();
+        let mut last = 0;
+        for _ in 0..len {
+            last += read_varint(&mut reader)?;
+            offsets.push(last);
+        }
+        let end = last + read_varint(&mut reader)?;
+        Ok(Self {
+            offsets,
+            end,
+            record_len,
+            counts,
+        })
+    }
+}
+
+fn header_counts(header: &EventrecordHeader) -> [u64; 3] {
+    [header.nevents, header.nsubevents, header.nreweights]
+}
+
+fn write_varint<W: Write>(writer: &mut W, mut n: u64) -> io::Result<()> {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut n = 0;
+    for shift in (0..u64::BITS).step_by(7) {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        n |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, "invalid varint"))
+}
+
+impl<R: BufRead + Seek> EventrecordReader<R> {
+    /// Continue reading from the `n`th event
+    ///
+    /// `n` can be equal to the number of events, in which case the
+    /// reader is positioned at the end of the record. Returns
+    /// [ReadErr::StaleIndex] if the index does not match the header
+    /// of the record.
+    pub fn seek_event(
+        &mut self,
+        index: &EventIndex,
+        n: usize,
+    ) -> Result<(), ReadErr> {
+        index.check_header(self.header())?;
+        let offset = if n == index.len() {
+            index.end()
+        } else {
+            index.offset(n).ok_or(ReadErr::NoSuchEvent(n))?
+        };
+        self.seek_to(offset, n as u64)?;
+        Ok(())
+    }
+
+    /// Read the events in the given range
+    pub fn read_range(
+        &mut self,
+        index: &EventIndex,
+        range: Range<usize>,
+    ) -> Result<Take<&mut Self>, ReadErr> {
+        if range.end > index.len() {
+            return Err(ReadErr::NoSuchEvent(range.end - 1));
+        }
+        self.seek_event(index, range.start)?;
+        Ok(self.take(range.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    use crate::{Event, Eventrecord};
+
+    fn record() -> String {
+        let mut record = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Eventrecord nevents="3" nsubevents="3" nreweights="3" as="2" name="Bm">
+<!--
+File generated with STRIPPER v0.1 for online data base
+-->
+"#
+        .to_owned();
+        for w in ["-0.1", "0.2", "0.3"] {
+            record += &format!(
+                r#"<e>
+<se w="{w}" muR="91.16253934" muF="91.16253934">
+<p id="1,21"> 5780.608219,0,0,5780.608219 </p>
+<p id="1,21"> 334.3891359,0,0,-334.3891359 </p>
+<p id="0,6"> 357.9061187,-58.25473457,9.621341818,-307.9843429 </p>
+<p id="0,-6"> 5757.091237,58.25473457,-9.621341818,5754.203426 </p>
+<rw ch="12"> 0.8893243414,0.05144448245,{w} </rw>
+</se>
+</e>
+"#
+            );
+        }
+        record += "</Eventrecord>\n";
+        record
+    }
+
+    #[test]
+    fn build_index() {
+        let record = record();
+        let index = EventIndex::build(record.as_bytes()).unwrap();
+        assert_eq!(index.len(), 3);
+        for &offset in index.offsets() {
+            assert!(record[offset as usize..].starts_with("<e>"));
+        }
+        assert!(record[index.end() as usize..].starts_with("</Eventrecord>"));
+
+        let mut out = Vec::new();
+        index.write(&mut out).unwrap();
+        assert_eq!(EventIndex::read(out.as_slice()).unwrap(), index);
+    }
+
+    #[test]
+    fn seek() {
+        let record = record();
+        let ref_record: Eventrecord = quick_xml::de::from_str(&record).unwrap();
+        let index = EventIndex::build(record.as_bytes()).unwrap();
+
+        let mut reader =
+            EventrecordReader::new(Cursor::new(record.as_bytes())).unwrap();
+        reader.seek_event(&index, 2).unwrap();
+        let events: Vec<Event> = reader.by_ref().map(Result::unwrap).collect();
+        assert_eq!(events, ref_record.events[2..]);
+
+        reader.seek_event(&index, 0).unwrap();
+        let event = reader.next().unwrap().unwrap();
+        assert_eq!(event, ref_record.events[0]);
+
+        let events: Vec<Event> = reader
+            .read_range(&index, 1..3)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(events, ref_record.events[1..3]);
+        assert!(reader.next().is_none());
+
+        reader.seek_event(&index, 3).unwrap();
+        assert!(reader.next().is_none());
+        assert!(matches!(
+            reader.seek_event(&index, 4),
+            Err(ReadErr::NoSuchEvent(4))
+        ));
+    }
+
+    #[test]
+    fn seek_malformed() {
+        let record = record();
+        let index = EventIndex::build(record.as_bytes()).unwrap();
+        let last = record.rfind("</se>").unwrap();
+        let mut broken = record.clone();
+        broken.replace_range(last..last + "</se>".len(), "</sx>");
+
+        let mut reader =
+            EventrecordReader::new(Cursor::new(broken.as_bytes())).unwrap();
+        reader.seek_event(&index, 1).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(reader.next(), Some(Err(ReadErr::Xml(_)))));
+    }
+
+    #[test]
+    fn stale_index() {
+        let dir = std::env::temp_dir()
+            .join(format!("stripper-xml-index-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let record_path = dir.join("record.xml");
+        let index_path = EventIndex::sidecar_path(&record_path);
+
+        let record = record();
+        std::fs::write(&record_path, &record).unwrap();
+        let index = EventIndex::build(record.as_bytes()).unwrap();
+        assert_eq!(index.record_len(), record.len() as u64);
+        index.save(&index_path).unwrap();
+        assert_eq!(EventIndex::load(&index_path, &record_path).unwrap(), index);
+
+        // same size, different header
+        let changed =
+            record.replacen("nsubevents=\"3\"", "nsubevents=\"4\"", 1);
+        std::fs::write(&record_path, &changed).unwrap();
+        assert!(matches!(
+            EventIndex::load(&index_path, &record_path),
+            Err(ReadErr::StaleIndex)
+        ));
+        let mut reader =
+            EventrecordReader::new(Cursor::new(changed.as_bytes())).unwrap();
+        assert!(matches!(
+            reader.seek_event(&index, 1),
+            Err(ReadErr::StaleIndex)
+        ));
+
+        // same header, different size
+        std::fs::write(&record_path, record.clone() + "\n").unwrap();
+        assert!(matches!(
+            EventIndex::load(&index_path, &record_path),
+            Err(ReadErr::StaleIndex)
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
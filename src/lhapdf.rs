@@ -0,0 +1,562 @@
+//! Reader for LHAPDF6 grid files
+//!
+//! A [PdfSet] is a directory containing a `<name>.info` metadata file
+//! and one `<name>_<member>.dat` grid file per member, as installed by
+//! LHAPDF. Only the `lhagrid1` grid format is supported.
+//!
+//! As in LHAPDF, parton densities are interpolated log-bicubically in
+//! `x` and `Q^2`. Outside the grid, the values at the nearest edge are
+//! used. If the metadata contain tabulated values of the strong
+//! coupling (`AlphaS_Type: ipol`), they are interpolated cubically in
+//! `log(Q^2)`.
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use particle_id::ParticleID;
+use thiserror::Error;
+
+use crate::reweight::PdfProvider;
+
+/// Metadata of a PDF set or member
+///
+/// This is a flat map from keys to values as found in `.info` files
+/// and the header of `.dat` files. Values are kept as text and parsed
+/// on access.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Info {
+    entries: HashMap<String, String>,
+}
+
+impl Info {
+    /// Parse the `key: value` entries in an `.info` file or the header
+    /// of a `.dat` file
+    pub fn parse(text: &str) -> Self {
+        let mut entries = HashMap::new();
+        let mut lines = text.lines();
+        while let Some(line) = lines.next() {
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let mut value = value.trim().to_owned();
+            // lists may continue over several lines
+            if value.starts_with('[') {
+                while !value.ends_with(']') {
+                    let Some(line) = lines.next() else {
+                        break;
+                    };
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+            }
+            entries.insert(key.trim().to_owned(), value);
+        }
+        Self { entries }
+    }
+
+    /// The value of an entry, without quotes
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let value = self.entries.get(key)?;
+        Some(value.trim_matches('"'))
+    }
+
+    /// Parse the value of a required entry
+    pub fn parse_value<T: FromStr>(&self, key: &str) -> Result<T, LhapdfErr> {
+        let value = self
+            .get(key)
+            .ok_or_else(|| LhapdfErr::MissingKey(key.to_owned()))?;
+        value.parse().map_err(|_| invalid_value(key, value))
+    }
+
+    /// Parse the value of a required entry of the form `[a, b, ...]`
+    pub fn parse_list<T: FromStr>(
+        &self,
+        key: &str,
+    ) -> Result<Vec<T>, LhapdfErr> {
+        let value = self
+            .get(key)
+            .ok_or_else(|| LhapdfErr::MissingKey(key.to_owned()))?;
+        let list = value
+            .strip_prefix('[')
+            .and_then(|v| v.strip_suffix(']'))
+            .ok_or_else(|| invalid_value(key, value))?;
+        list.split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(|v| v.parse().map_err(|_| invalid_value(key, value)))
+            .collect()
+    }
+
+    // add the entries of `other`, replacing existing ones
+    fn extend(&mut self, other: Info) {
+        self.entries.extend(other.entries)
+    }
+}
+
+fn invalid_value(key: &str, value: &str) -> LhapdfErr {
+    LhapdfErr::InvalidValue {
+        key: key.to_owned(),
+        value: value.to_owned(),
+    }
+}
+
+/// A PDF set in a local directory
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PdfSet {
+    dir: PathBuf,
+    name: String,
+    info: Info,
+}
+
+impl PdfSet {
+    /// Open the set in the given directory
+    ///
+    /// The name of the set is the name of the directory.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, LhapdfErr> {
+        let dir = dir.as_ref().to_owned();
+        let name = dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| LhapdfErr::InvalidSetPath(dir.clone()))?
+            .to_owned();
+        let info = fs::read_to_string(dir.join(format!("{name}.info")))?;
+        let info = Info::parse(&info);
+        Ok(Self { dir, name, info })
+    }
+
+    /// The name of the set
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The metadata of the set
+    pub fn info(&self) -> &Info {
+        &self.info
+    }
+
+    /// The number of members
+    pub fn num_members(&self) -> Result<usize, LhapdfErr> {
+        self.info.parse_value("NumMembers")
+    }
+
+    /// Load the given member
+    pub fn member(&self, member: usize) -> Result<GridPdf, LhapdfErr> {
+        if member >= self.num_members()? {
+            return Err(LhapdfErr::NoSuchMember(member));
+        }
+        let path = self.dir.join(format!("{}_{member:04}.dat", self.name));
+        let grid = fs::read_to_string(path)?;
+        GridPdf::parse(self.info.clone(), &grid)
+    }
+
+    /// Load all members
+    pub fn members(&self) -> Result<Vec<GridPdf>, LhapdfErr> {
+        (0..self.num_members()?).map(|n| self.member(n)).collect()
+    }
+}
+
+/// A PDF set member interpolating an `lhagrid1` grid
+#[derive(Clone, Debug, PartialEq)]
+pub struct GridPdf {
+    info: Info,
+    subgrids: Vec<Subgrid>,
+    alpha_s: Option<AlphaSGrid>,
+}
+
+impl GridPdf {
+    /// Parse a member grid file
+    ///
+    /// The entries in the header of the grid file are added to the
+    /// metadata `info` of the set.
+    pub fn parse(mut info: Info, grid: &str) -> Result<Self, LhapdfErr> {
+        const SEPARATOR: &str = "---";
+
+        let mut blocks = grid
+            .lines()
+            .collect::<Vec<_>>()
+            .split(|line| line.trim() == SEPARATOR)
+            .map(|lines| lines.join("\n"))
+            .collect::<Vec<_>>()
+            .into_iter();
+        let header = blocks.next().unwrap_or_default();
+        info.extend(Info::parse(&header));
+        let format = info.get("Format").unwrap_or_default();
+        if format != "lhagrid1" {
+            return Err(LhapdfErr::UnsupportedFormat(format.to_owned()));
+        }
+        let subgrids = blocks
+            .filter(|block| !block.trim().is_empty())
+            .map(|block| Subgrid::parse(&block))
+            .collect::<Result<Vec<_>, _>>()?;
+        if subgrids.is_empty() {
+            return Err(LhapdfErr::InvalidGrid("no subgrids"));
+        }
+        let alpha_s = match info.get("AlphaS_Type") {
+            Some("ipol") => Some(AlphaSGrid::new(
+                &info.parse_list("AlphaS_Qs")?,
+                &info.parse_list("AlphaS_Vals")?,
+            )?),
+            _ => None,
+        };
+        Ok(Self {
+            info,
+            subgrids,
+            alpha_s,
+        })
+    }
+
+    /// The metadata of the member, including the set metadata
+    pub fn info(&self) -> &Info {
+        &self.info
+    }
+
+    // the subgrid for the given log(Q^2)
+    fn subgrid(&self, logq2: f64) -> &Subgrid {
+        let pos = self
+            .subgrids
+            .partition_point(|grid| grid.logq2s[0] <= logq2);
+        &self.subgrids[pos.saturating_sub(1)]
+    }
+}
+
+impl PdfProvider for GridPdf {
+    fn xfx_q2(&self, id: ParticleID, x: f64, q2: f64) -> f64 {
+        // LHAPDF also accepts 0 for gluons
+        let id = if id.id() == 0 { 21 } else { id.id() };
+        let subgrid = self.subgrid(q2.ln());
+        let Some(flavour) = subgrid.flavours.iter().position(|&f| f == id)
+        else {
+            return 0.;
+        };
+        subgrid.xf(flavour, x.ln(), q2.ln())
+    }
+
+    fn alpha_s_q2(&self, q2: f64) -> Option<f64> {
+        self.alpha_s.as_ref().map(|alpha_s| alpha_s.at_q2(q2))
+    }
+}
+
+// A grid covering a range in Q^2
+#[derive(Clone, Debug, PartialEq)]
+struct Subgrid {
+    logxs: Vec<f64>,
+    logq2s: Vec<f64>,
+    flavours: Vec<i32>,
+    // x f(x, Q^2) in the order x, Q^2, flavour
+    values: Vec<f64>,
+}
+
+impl Subgrid {
+    fn parse(block: &str) -> Result<Self, LhapdfErr> {
+        fn parse_line<T: FromStr>(
+            line: Option<&str>,
+            what: &'static str,
+        ) -> Result<Vec<T>, LhapdfErr> {
+            let line = line.ok_or(LhapdfErr::InvalidGrid(what))?;
+            line.split_whitespace()
+                .map(|v| v.parse().map_err(|_| LhapdfErr::InvalidGrid(what)))
+                .collect()
+        }
+
+        let mut lines = block.lines().filter(|l| !l.trim().is_empty());
+        let xs: Vec<f64> = parse_line(lines.next(), "invalid x knots")?;
+        let qs: Vec<f64> = parse_line(lines.next(), "invalid Q knots")?;
+        let flavours = parse_line(lines.next(), "invalid flavours")?;
+        if xs.len() < 2 || qs.len() < 2 {
+            return Err(LhapdfErr::InvalidGrid("fewer than two knots"));
+        }
+        let mut values =
+            Vec::with_capacity(xs.len() * qs.len() * flavours.len());
+        for line in lines {
+            let row: Vec<f64> = parse_line(Some(line), "invalid values")?;
+            if row.len() != flavours.len() {
+                return Err(LhapdfErr::InvalidGrid("wrong number of flavours"));
+            }
+            values.extend(row);
+        }
+        if values.len() != xs.len() * qs.len() * flavours.len() {
+            return Err(LhapdfErr::InvalidGrid("wrong number of values"));
+        }
+        Ok(Self {
+            logxs: xs.iter().map(|x| x.ln()).collect(),
+            logq2s: qs.iter().map(|q| (q * q).ln()).collect(),
+            flavours,
+            values,
+        })
+    }
+
+    fn value(&self, ix: usize, iq: usize, flavour: usize) -> f64 {
+        let nq = self.logq2s.len();
+        let nfl = self.flavours.len();
+        self.values[(ix * nq + iq) * nfl + flavour]
+    }
+
+    // derivative in log(x) at a knot from finite differences
+    fn ddlogx(&self, ix: usize, iq: usize, flavour: usize) -> f64 {
+        let v = |ix| self.value(ix, iq, flavour);
+        let slope = |ix: usize| {
+            (v(ix + 1) - v(ix)) / (self.logxs[ix + 1] - self.logxs[ix])
+        };
+        if ix == 0 {
+            slope(0)
+        } else if ix + 1 == self.logxs.len() {
+            slope(ix - 1)
+        } else {
+            (slope(ix - 1) + slope(ix)) / 2.
+        }
+    }
+
+    fn xf(&self, flavour: usize, logx: f64, logq2: f64) -> f64 {
+        let logx = clamp(&self.logxs, logx);
+        let logq2 = clamp(&self.logq2s, logq2);
+        let ix = knot_index(&self.logxs, logx);
+        let iq = knot_index(&self.logq2s, logq2);
+
+        // interpolate in x at the Q^2 knots
+        let dlogx = self.logxs[ix + 1] - self.logxs[ix];
+        let tx = (logx - self.logxs[ix]) / dlogx;
+        let at_q = |iq| {
+            interpolate_cubic(
+                tx,
+                self.value(ix, iq, flavour),
+                self.ddlogx(ix, iq, flavour) * dlogx,
+                self.value(ix + 1, iq, flavour),
+                self.ddlogx(ix + 1, iq, flavour) * dlogx,
+            )
+        };
+
+        // interpolate in Q^2, with derivatives from finite differences
+        let logq2s = &self.logq2s;
+        let dlogq2 = logq2s[iq + 1] - logq2s[iq];
+        let tq = (logq2 - logq2s[iq]) / dlogq2;
+        let (low, high) = (at_q(iq), at_q(iq + 1));
+        let slope = (high - low) / dlogq2;
+        let dlow = if iq == 0 {
+            slope
+        } else {
+            let prev = (low - at_q(iq - 1)) / (logq2s[iq] - logq2s[iq - 1]);
+            (prev + slope) / 2.
+        };
+        let dhigh = if iq + 2 == logq2s.len() {
+            slope
+        } else {
+            let next =
+                (at_q(iq + 2) - high) / (logq2s[iq + 2] - logq2s[iq + 1]);
+            (slope + next) / 2.
+        };
+        interpolate_cubic(tq, low, dlow * dlogq2, high, dhigh * dlogq2)
+    }
+}
+
+// Tabulated strong coupling
+#[derive(Clone, Debug, PartialEq)]
+struct AlphaSGrid {
+    // one subgrid for each flavour number, split at repeated knots
+    subgrids: Vec<(Vec<f64>, Vec<f64>)>,
+}
+
+impl AlphaSGrid {
+    fn new(qs: &[f64], values: &[f64]) -> Result<Self, LhapdfErr> {
+        if qs.len() != values.len() || qs.len() < 2 {
+            return Err(LhapdfErr::InvalidGrid("invalid alpha_s knots"));
+        }
+        let mut subgrids = Vec::new();
+        let mut logq2s = Vec::new();
+        let mut alpha_s = Vec::new();
+        for (&q, &value) in qs.iter().zip(values) {
+            let logq2 = (q * q).ln();
+            if logq2s.last() == Some(&logq2) {
+                subgrids.push((
+                    std::mem::take(&mut logq2s),
+                    std::mem::take(&mut alpha_s),
+                ));
+            }
+            logq2s.push(logq2);
+            alpha_s.push(value);
+        }
+        subgrids.push((logq2s, alpha_s));
+        if subgrids.iter().any(|(logq2s, _)| logq2s.len() < 2) {
+            return Err(LhapdfErr::InvalidGrid("invalid alpha_s knots"));
+        }
+        Ok(Self { subgrids })
+    }
+
+    fn at_q2(&self, q2: f64) -> f64 {
+        let logq2 = q2.ln();
+        let (first_logq2s, first_vals) = &self.subgrids[0];
+        if logq2 < first_logq2s[0] {
+            // extrapolate as a power law
+            let slope = (first_vals[1] / first_vals[0]).ln()
+                / (first_logq2s[1] - first_logq2s[0]);
+            return first_vals[0] * (slope * (logq2 - first_logq2s[0])).exp();
+        }
+        let pos = self
+            .subgrids
+            .partition_point(|(logq2s, _)| logq2s[0] <= logq2);
+        let (logq2s, vals) = &self.subgrids[pos - 1];
+        if logq2 >= *logq2s.last().unwrap() && pos == self.subgrids.len() {
+            return *vals.last().unwrap();
+        }
+        let i = knot_index(logq2s, logq2);
+        let slope =
+            |i: usize| (vals[i + 1] - vals[i]) / (logq2s[i + 1] - logq2s[i]);
+        let derivative = |i: usize| {
+            if i == 0 {
+                slope(0)
+            } else if i + 1 == logq2s.len() {
+                slope(i - 1)
+            } else {
+                (slope(i - 1) + slope(i)) / 2.
+            }
+        };
+        let dlogq2 = logq2s[i + 1] - logq2s[i];
+        let t = (logq2 - logq2s[i]) / dlogq2;
+        interpolate_cubic(
+            t,
+            vals[i],
+            derivative(i) * dlogq2,
+            vals[i + 1],
+            derivative(i + 1) * dlogq2,
+        )
+    }
+}
+
+// the index `i` of the knot interval `[knots[i], knots[i+1]]`
+// containing `v`
+fn knot_index(knots: &[f64], v: f64) -> usize {
+    let pos = knots.partition_point(|k| *k <= v);
+    pos.saturating_sub(1).min(knots.len() - 2)
+}
+
+fn clamp(knots: &[f64], v: f64) -> f64 {
+    v.clamp(knots[0], *knots.last().unwrap())
+}
+
+// cubic Hermite interpolation on the unit interval
+fn interpolate_cubic(t: f64, vl: f64, dl: f64, vh: f64, dh: f64) -> f64 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (2. * t3 - 3. * t2 + 1.) * vl
+        + (t3 - 2. * t2 + t) * dl
+        + (-2. * t3 + 3. * t2) * vh
+        + (t3 - t2) * dh
+}
+
+#[derive(Debug, Error)]
+pub enum LhapdfErr {
+    #[error("Failed to read PDF set")]
+    Io(#[from] std::io::Error),
+    #[error("'{0}' is not a valid PDF set directory")]
+    InvalidSetPath(PathBuf),
+    #[error("Missing metadata entry `{0}`")]
+    MissingKey(String),
+    #[error("Invalid value '{value}' for metadata entry `{key}`")]
+    InvalidValue { key: String, value: String },
+    #[error("Unsupported grid format '{0}'")]
+    UnsupportedFormat(String),
+    #[error("Invalid grid: {0}")]
+    InvalidGrid(&'static str),
+    #[error("PDF set has no member {0}")]
+    NoSuchMember(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::rel_diff;
+
+    const TOY_GRID: &str =
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/lhapdf/ToyGrid");
+
+    // the parton densities used to generate the toy grid
+    fn toy_xf(id: i32, x: f64, q: f64) -> f64 {
+        let evol = 1. + 0.05 * (q * q / 2.).ln();
+        match id {
+            21 => 1.7 * x.powf(-0.1) * (1. - x).powi(5) * evol,
+            3 => 0.1 + 0.01 * x.ln() + 0.02 * (q * q).ln(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn info() {
+        let set = PdfSet::open(TOY_GRID).unwrap();
+        assert_eq!(set.name(), "ToyGrid");
+        let info = set.info();
+        assert_eq!(
+            info.get("SetDesc"),
+            Some("Toy PDF grid for the stripper-xml tests")
+        );
+        assert_eq!(set.num_members().unwrap(), 1);
+        assert_eq!(
+            info.parse_list::<i32>("Flavors").unwrap(),
+            [-2, -1, 1, 2, 3, 21]
+        );
+        assert!(matches!(
+            info.parse_value::<f64>("ErrorType"),
+            Err(LhapdfErr::InvalidValue { .. })
+        ));
+        assert!(matches!(set.member(1), Err(LhapdfErr::NoSuchMember(1))));
+
+        let info = Info::parse("Qs: [1, 2,\n  3]\n# comment: 1\n");
+        assert_eq!(info.parse_list::<f64>("Qs").unwrap(), [1., 2., 3.]);
+        assert_eq!(info.get("# comment"), None);
+    }
+
+    #[test]
+    fn interpolate_xf() {
+        let pdf = PdfSet::open(TOY_GRID).unwrap().member(0).unwrap();
+        assert_eq!(pdf.info().get("PdfType"), Some("central"));
+        let gluon = ParticleID::new(21);
+        let strange = ParticleID::new(3);
+
+        // exact at the knots
+        for (x, q) in [(1e-3, 1.4), (0.1, 3.), (0.4, 4.5), (0.8, 300.)] {
+            let xf = pdf.xfx_q2(gluon, x, q * q);
+            assert!(rel_diff(xf, toy_xf(21, x, q)) < 1e-7);
+        }
+        // exact for functions linear in log(x) and log(Q^2)
+        for (x, q) in [(2e-3, 1.5), (0.05, 3.7), (0.33, 20.), (0.9, 250.)] {
+            let xf = pdf.xfx_q2(strange, x, q * q);
+            assert!(rel_diff(xf, toy_xf(3, x, q)) < 1e-7);
+        }
+        // approximate in between
+        for (x, q) in [(2e-3, 1.5), (0.05, 3.7), (0.15, 20.), (0.3, 250.)] {
+            let xf = pdf.xfx_q2(gluon, x, q * q);
+            assert!(rel_diff(xf, toy_xf(21, x, q)) < 2e-2);
+        }
+        // gluons can also be given as 0
+        assert_eq!(
+            pdf.xfx_q2(ParticleID::new(0), 0.1, 100.),
+            pdf.xfx_q2(gluon, 0.1, 100.)
+        );
+        assert_eq!(pdf.xfx_q2(ParticleID::new(5), 0.1, 100.), 0.);
+        // nearest-edge extrapolation
+        assert_eq!(
+            pdf.xfx_q2(gluon, 1e-5, 1.),
+            pdf.xfx_q2(gluon, 1e-3, 1.4 * 1.4)
+        );
+    }
+
+    #[test]
+    fn interpolate_alpha_s() {
+        let pdf = PdfSet::open(TOY_GRID).unwrap().member(0).unwrap();
+        let mz = 91.1876f64;
+        let alpha_s = pdf.alpha_s_q2(mz * mz).unwrap();
+        assert!(rel_diff(alpha_s, 0.118) < 1e-7);
+        let b0 = 23. / (12. * std::f64::consts::PI);
+        for q in [1.7f64, 4., 7., 50., 200.] {
+            let expected = 0.118 / (1. + b0 * 0.118 * (q * q / (mz * mz)).ln());
+            let alpha_s = pdf.alpha_s_q2(q * q).unwrap();
+            assert!(rel_diff(alpha_s, expected) < 5e-3);
+        }
+        let last = pdf.alpha_s_q2(300. * 300.).unwrap();
+        assert_eq!(pdf.alpha_s_q2(1e6).unwrap(), last);
+        let first = pdf.alpha_s_q2(1.4 * 1.4).unwrap();
+        assert!(pdf.alpha_s_q2(1.).unwrap() > first);
+    }
+}
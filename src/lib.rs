@@ -1,7 +1,23 @@
+pub mod alpha_s;
 pub mod channels;
+pub mod compression;
 pub mod event;
+pub mod format;
 #[cfg(feature = "hepmc2")]
 pub mod hepmc;
+pub mod index;
+pub mod lhapdf;
 pub mod normalization;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod parser;
+pub mod pdf_uncertainty;
+pub mod reweight;
+pub mod scale_variation;
+pub mod scales;
+#[cfg(test)]
+mod test_util;
+pub mod validate;
+pub mod version;
 
 pub use event::*;
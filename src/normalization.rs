@@ -1,6 +1,11 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use quick_xml::escape::escape;
 use serde::{Deserialize, Serialize, Serializer};
 
-use crate::ParseErr;
+use crate::format::WriteOptions;
+use crate::{parse_float, write_generator_comment, ParseErr, WriteXML};
 
 #[derive(
     Deserialize, Serialize, Clone, Debug, Default, PartialEq, PartialOrd,
@@ -14,58 +19,179 @@ pub struct Normalization {
     #[serde(rename = "Contribution")]
     pub contribution: Contribution,
     #[serde(rename = "NumberOfRejectedEvents")]
-    pub number_of_rejected_events: String,
-}
-
-// // TODO: this would be the proper way to do this, but it doesn't work
-// #[derive(Deserialize, Serialize)]
-// #[derive(Clone, Debug, PartialEq, PartialOrd)]
-// #[serde(untagged)]
-// pub enum XSection {
-//     Neg(XSectionNeg),
-//     Pos(XSectionPos),
-// }
-
-// #[derive(Deserialize, Serialize)]
-// #[derive(Clone, Debug, PartialEq, PartialOrd)]
-// #[serde(rename_all = "PascalCase")]
-// pub struct XSectionNeg {
-//     #[serde(rename = "XSNeg")]
-//     pub xs_neg: XSScale,
-//     pub max_weight_neg: f64,
-//     pub total_events_neg: u64,
-//     pub accepted_events_neg: u64,
-//     pub factor_neg: String,
-// }
-
-// #[derive(Deserialize, Serialize)]
-// #[derive(Clone, Debug, PartialEq, PartialOrd)]
-// #[serde(rename_all = "PascalCase")]
-// pub struct XSectionPos {
-//     #[serde(rename = "XSPos")]
-//     pub xs_pos: XSScale,
-//     pub max_weight_pos: f64,
-//     pub total_events_pos: u64,
-//     pub accepted_events_pos: u64,
-//     pub factor_pos: String,
-// }
+    pub number_of_rejected_events: NumberOfRejectedEvents,
+}
+
+/// Cross section of the events with either negative or positive weight
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+pub enum XSection {
+    Neg(XSectionNeg),
+    Pos(XSectionPos),
+}
+
+impl Default for XSection {
+    fn default() -> Self {
+        Self::Pos(XSectionPos::default())
+    }
+}
+
+impl XSection {
+    /// Whether this is the cross section of negative-weight events
+    pub fn is_neg(&self) -> bool {
+        matches!(self, Self::Neg(_))
+    }
+
+    /// Whether this is the cross section of positive-weight events
+    pub fn is_pos(&self) -> bool {
+        matches!(self, Self::Pos(_))
+    }
+
+    /// The cross section and its error
+    pub fn xs(&self) -> &XSScale {
+        match self {
+            Self::Neg(xs) => &xs.xs_neg,
+            Self::Pos(xs) => &xs.xs_pos,
+        }
+    }
+
+    /// The maximum weight
+    pub fn max_weight(&self) -> f64 {
+        match self {
+            Self::Neg(xs) => xs.max_weight_neg,
+            Self::Pos(xs) => xs.max_weight_pos,
+        }
+    }
+
+    /// The total number of events
+    pub fn total_events(&self) -> u64 {
+        match self {
+            Self::Neg(xs) => xs.total_events_neg,
+            Self::Pos(xs) => xs.total_events_pos,
+        }
+    }
+
+    /// The number of accepted events
+    pub fn accepted_events(&self) -> u64 {
+        match self {
+            Self::Neg(xs) => xs.accepted_events_neg,
+            Self::Pos(xs) => xs.accepted_events_pos,
+        }
+    }
+
+    /// The normalisation factor
+    pub fn factor(&self) -> Factor {
+        match self {
+            Self::Neg(xs) => xs.factor_neg,
+            Self::Pos(xs) => xs.factor_pos,
+        }
+    }
+}
 
 #[derive(
     Deserialize, Serialize, Clone, Debug, Default, PartialEq, PartialOrd,
 )]
-#[serde(rename_all = "PascalCase")]
-pub struct XSection {
-    // TODO: the `alias` is an evil hack and breaks serialization
-    #[serde(rename = "XSPos", alias = "XSNeg")]
+#[serde(rename = "XSection", rename_all = "PascalCase")]
+pub struct XSectionNeg {
+    #[serde(rename = "XSNeg")]
+    pub xs_neg: XSScale,
+    pub max_weight_neg: f64,
+    pub total_events_neg: u64,
+    pub accepted_events_neg: u64,
+    pub factor_neg: Factor,
+}
+
+#[derive(
+    Deserialize, Serialize, Clone, Debug, Default, PartialEq, PartialOrd,
+)]
+#[serde(rename = "XSection", rename_all = "PascalCase")]
+pub struct XSectionPos {
+    #[serde(rename = "XSPos")]
     pub xs_pos: XSScale,
-    #[serde(alias = "MaxWeightNeg")]
     pub max_weight_pos: f64,
-    #[serde(alias = "TotalEventsNeg")]
     pub total_events_pos: u64,
-    #[serde(alias = "AcceptedEventsNeg")]
     pub accepted_events_pos: u64,
-    #[serde(alias = "FactorNeg")]
-    pub factor_pos: String,
+    pub factor_pos: Factor,
+}
+
+// `#[serde(untagged)]` doesn't work for `XSection`, because the
+// buffered element content can only be deserialised as strings.
+// Instead, we collect all possible fields and decide afterwards.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct XSectionFields {
+    #[serde(rename = "XSNeg")]
+    xs_neg: Option<XSScale>,
+    max_weight_neg: Option<f64>,
+    total_events_neg: Option<u64>,
+    accepted_events_neg: Option<u64>,
+    factor_neg: Option<Factor>,
+    #[serde(rename = "XSPos")]
+    xs_pos: Option<XSScale>,
+    max_weight_pos: Option<f64>,
+    total_events_pos: Option<u64>,
+    accepted_events_pos: Option<u64>,
+    factor_pos: Option<Factor>,
+}
+
+impl<'de> Deserialize<'de> for XSection {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::Error;
+        fn required<T, E: Error>(
+            field: Option<T>,
+            name: &'static str,
+        ) -> Result<T, E> {
+            field.ok_or_else(|| E::missing_field(name))
+        }
+
+        let f = XSectionFields::deserialize(deserializer)?;
+        match (f.xs_neg, f.xs_pos) {
+            (Some(xs_neg), None) => Ok(Self::Neg(XSectionNeg {
+                xs_neg,
+                max_weight_neg: required(f.max_weight_neg, "MaxWeightNeg")?,
+                total_events_neg: required(
+                    f.total_events_neg,
+                    "TotalEventsNeg",
+                )?,
+                accepted_events_neg: required(
+                    f.accepted_events_neg,
+                    "AcceptedEventsNeg",
+                )?,
+                factor_neg: required(f.factor_neg, "FactorNeg")?,
+            })),
+            (None, Some(xs_pos)) => Ok(Self::Pos(XSectionPos {
+                xs_pos,
+                max_weight_pos: required(f.max_weight_pos, "MaxWeightPos")?,
+                total_events_pos: required(
+                    f.total_events_pos,
+                    "TotalEventsPos",
+                )?,
+                accepted_events_pos: required(
+                    f.accepted_events_pos,
+                    "AcceptedEventsPos",
+                )?,
+                factor_pos: required(f.factor_pos, "FactorPos")?,
+            })),
+            (Some(_), Some(_)) => {
+                Err(D::Error::custom("Found both `XSNeg` and `XSPos`"))
+            }
+            (None, None) => Err(D::Error::missing_field("XSPos")),
+        }
+    }
+}
+
+impl Serialize for XSection {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Self::Neg(xs) => xs.serialize(serializer),
+            Self::Pos(xs) => xs.serialize(serializer),
+        }
+    }
 }
 
 #[derive(
@@ -94,6 +220,18 @@ pub struct Reweight {
     pub rwentry: Vec<String>,
 }
 
+impl Reweight {
+    /// The number of log coefficients in each event reweighting entry
+    ///
+    /// Apart from the momentum fractions `x1` and `x2`, each entry
+    /// names a logarithm. There is one coefficient for each of them
+    /// and an additional one for the constant term.
+    pub fn nlog_coeff(&self) -> usize {
+        let is_x = |e: &str| matches!(e.trim(), "x1" | "x2");
+        1 + self.rwentry.iter().filter(|e| !is_x(e)).count()
+    }
+}
+
 #[derive(Clone, Debug, Default, PartialEq, PartialOrd)]
 pub struct XSScale(pub [f64; 2]);
 
@@ -124,6 +262,186 @@ impl<'de> Deserialize<'de> for XSScale {
     }
 }
 
+impl WriteXML for Normalization {
+    type Error = std::io::Error;
+
+    fn write_with<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &WriteOptions,
+    ) -> Result<(), Self::Error> {
+        writeln!(
+            writer,
+            "<Normalization name=\"{}\">",
+            escape(self.name.as_str())
+        )?;
+        write_generator_comment(writer)?;
+        self.xsection.write_with(writer, options)?;
+        self.contribution.write_with(writer, options)?;
+        let NumberOfRejectedEvents { rejected, total } =
+            self.number_of_rejected_events;
+        writeln!(
+            writer,
+            "<NumberOfRejectedEvents> {rejected} , {total}</NumberOfRejectedEvents>",
+        )?;
+        writeln!(writer, "</Normalization>")
+    }
+}
+
+impl WriteXML for XSection {
+    type Error = std::io::Error;
+
+    fn write_with<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &WriteOptions,
+    ) -> Result<(), Self::Error> {
+        let sign = if self.is_neg() { "Neg" } else { "Pos" };
+        let mut xs = String::new();
+        options.write_floats(&mut xs, self.xs().0);
+        let mut max_weight = String::new();
+        options.write_float(&mut max_weight, self.max_weight());
+        let Factor { value, error } = self.factor();
+        let mut factor = String::new();
+        options.write_floats(&mut factor, [value, error]);
+        writeln!(writer, "<XSection>")?;
+        writeln!(writer, " <XS{sign}> {xs} </XS{sign}>")?;
+        writeln!(writer, " <MaxWeight{sign}> {max_weight} </MaxWeight{sign}>")?;
+        writeln!(
+            writer,
+            " <TotalEvents{sign}> {} </TotalEvents{sign}>",
+            self.total_events()
+        )?;
+        writeln!(
+            writer,
+            " <AcceptedEvents{sign}> {} </AcceptedEvents{sign}>",
+            self.accepted_events()
+        )?;
+        writeln!(writer, " <Factor{sign}> {factor} </Factor{sign}>")?;
+        writeln!(writer, "</XSection>")
+    }
+}
+
+impl WriteXML for Contribution {
+    type Error = std::io::Error;
+
+    fn write_with<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &WriteOptions,
+    ) -> Result<(), Self::Error> {
+        let mut xs = String::new();
+        options.write_floats(&mut xs, self.xsection.0);
+        writeln!(
+            writer,
+            "<Contribution name=\"{}\">",
+            escape(self.name.as_str())
+        )?;
+        writeln!(writer, "  <xsection> {xs}</xsection>")?;
+        writeln!(writer, "  <rw>")?;
+        for entry in &self.rw.rwentry {
+            writeln!(
+                writer,
+                "    <rwentry> {} </rwentry>",
+                escape(entry.as_str())
+            )?;
+        }
+        writeln!(writer, "  </rw>")?;
+        writeln!(writer, "</Contribution>")
+    }
+}
+
+/// The normalisation factor and its error
+#[derive(Copy, Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct Factor {
+    pub value: f64,
+    pub error: f64,
+}
+
+impl FromStr for Factor {
+    type Err = ParseErr;
+
+    fn from_str(factor_str: &str) -> Result<Self, Self::Err> {
+        let [value, error] = split_pair(factor_str)?;
+        Ok(Self {
+            value: parse_float(value)?,
+            error: parse_float(error)?,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Factor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let factor_str = String::deserialize(deserializer)?;
+        factor_str.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Factor {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format!("{},{}", self.value, self.error))
+    }
+}
+
+/// The number of rejected events out of the total number of events
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct NumberOfRejectedEvents {
+    pub rejected: u64,
+    pub total: u64,
+}
+
+impl FromStr for NumberOfRejectedEvents {
+    type Err = ParseErr;
+
+    fn from_str(events_str: &str) -> Result<Self, Self::Err> {
+        let [rejected, total] = split_pair(events_str)?;
+        Ok(Self {
+            rejected: parse_count(rejected)?,
+            total: parse_count(total)?,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for NumberOfRejectedEvents {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let events_str = String::deserialize(deserializer)?;
+        events_str.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for NumberOfRejectedEvents {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format!("{},{}", self.rejected, self.total))
+    }
+}
+
+// split a comma-separated pair of entries
+fn split_pair(s: &str) -> Result<[&str; 2], ParseErr> {
+    let mut entries = s.split(',');
+    match (entries.next(), entries.next(), entries.next()) {
+        (Some(first), Some(second), None) => Ok([first, second]),
+        _ => Err(ParseErr::NumEntries(s.to_owned(), 2)),
+    }
+}
+
+fn parse_count(s: &str) -> Result<u64, ParseErr> {
+    s.trim()
+        .parse()
+        .map_err(|_| ParseErr::InvalidCount(s.to_owned()))
+}
+
 impl Serialize for XSScale {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -147,7 +465,9 @@ mod tests {
  <AcceptedEventsNeg> 493310 </AcceptedEventsNeg>
  <FactorNeg> 803.98,1.14468 </FactorNeg>
 </XSection>"#;
-        let _xs: XSection = quick_xml::de::from_str(REF_XS).unwrap();
+        let xs: XSection = quick_xml::de::from_str(REF_XS).unwrap();
+        assert!(xs.is_neg());
+        assert_eq!(xs.total_events(), 488338734);
     }
 
     #[test]
@@ -159,12 +479,36 @@ mod tests {
  <AcceptedEventsPos> 493310 </AcceptedEventsPos>
  <FactorPos> 803.98,1.14468 </FactorPos>
 </XSection>"#;
-        let _xs: XSection = quick_xml::de::from_str(REF_XS).unwrap();
+        let xs: XSection = quick_xml::de::from_str(REF_XS).unwrap();
+        assert!(xs.is_pos());
+        assert_eq!(
+            xs.factor(),
+            Factor {
+                value: 803.98,
+                error: 1.14468
+            }
+        );
+
+        let mut out = Vec::new();
+        xs.write(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{REF_XS}\n"));
     }
 
     #[test]
-    fn deser_normalization() {
-        pub const REF_NORM: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+    fn deser_xsection_invalid() {
+        let mixed = r#"<XSection>
+ <XSNeg> 687.103,0.978277 </XSNeg>
+ <XSPos> 687.103,0.978277 </XSPos>
+</XSection>"#;
+        assert!(quick_xml::de::from_str::<XSection>(mixed).is_err());
+        let incomplete = r#"<XSection>
+ <XSNeg> 687.103,0.978277 </XSNeg>
+ <MaxWeightNeg> 796475 </MaxWeightNeg>
+</XSection>"#;
+        assert!(quick_xml::de::from_str::<XSection>(incomplete).is_err());
+    }
+
+    pub const REF_NORM: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
 <Normalization name="Cm">
 <!--
 File generated with STRIPPER v0.1 for online data base
@@ -188,6 +532,9 @@ File generated with STRIPPER v0.1 for online data base
 <NumberOfRejectedEvents> 0 , 100</NumberOfRejectedEvents>
 </Normalization>
 "#;
+
+    #[test]
+    fn deser_normalization() {
         let norm: Normalization = quick_xml::de::from_str(REF_NORM).unwrap();
         assert_eq!(norm.name, "Cm");
         assert_eq!(norm.contribution.name, "Cm");
@@ -196,17 +543,71 @@ File generated with STRIPPER v0.1 for online data base
             norm.contribution.rw.rwentry,
             ["x1", "x2", "log(muR**2)", "log(muF**2)"]
         );
-        let XSection {
-            xs_pos,
-            max_weight_pos,
-            total_events_pos,
-            accepted_events_pos,
-            factor_pos,
-        } = norm.xsection;
-        assert_eq!(xs_pos, XSScale([687.103, 0.978277]));
-        assert_eq!(max_weight_pos, 796475.);
-        assert_eq!(accepted_events_pos, 493310);
-        assert_eq!(total_events_pos, 488338734);
-        assert_eq!(factor_pos, "803.98,1.14468");
+        let XSection::Neg(XSectionNeg {
+            xs_neg,
+            max_weight_neg,
+            total_events_neg,
+            accepted_events_neg,
+            factor_neg,
+        }) = norm.xsection
+        else {
+            panic!("expected negative-weight cross section");
+        };
+        assert_eq!(xs_neg, XSScale([687.103, 0.978277]));
+        assert_eq!(max_weight_neg, 796475.);
+        assert_eq!(accepted_events_neg, 493310);
+        assert_eq!(total_events_neg, 488338734);
+        assert_eq!(factor_neg.value, 803.98);
+        assert_eq!(factor_neg.error, 1.14468);
+        assert_eq!(
+            norm.number_of_rejected_events,
+            NumberOfRejectedEvents {
+                rejected: 0,
+                total: 100
+            }
+        );
+    }
+
+    #[test]
+    fn ser_normalization() {
+        let norm: Normalization = quick_xml::de::from_str(REF_NORM).unwrap();
+        let mut out = br#"<?xml version="1.0" encoding="UTF-8"?>
+"#
+        .to_vec();
+        norm.write(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let norm_2: Normalization = quick_xml::de::from_str(&out).unwrap();
+        assert_eq!(norm, norm_2);
+
+        let comment = "File generated with STRIPPER v0.1 for online data base";
+        let generator = format!(
+            "Record generated with {} {}",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        );
+        assert_eq!(out, REF_NORM.replace(comment, &generator));
+    }
+
+    #[test]
+    fn parse_counts_and_factors() {
+        let events: NumberOfRejectedEvents = " 3 , 100".parse().unwrap();
+        assert_eq!(events.rejected, 3);
+        assert_eq!(events.total, 100);
+        assert!(matches!(
+            "3".parse::<NumberOfRejectedEvents>(),
+            Err(ParseErr::NumEntries(_, 2))
+        ));
+        assert!(matches!(
+            "3,-1".parse::<NumberOfRejectedEvents>(),
+            Err(ParseErr::InvalidCount(_))
+        ));
+        assert!(matches!(
+            "1,2,3".parse::<Factor>(),
+            Err(ParseErr::NumEntries(_, 2))
+        ));
+        assert!(matches!(
+            "1,x".parse::<Factor>(),
+            Err(ParseErr::InvalidFloat(_))
+        ));
     }
 }
@@ -0,0 +1,210 @@
+//! Parallel parsing of event records
+//!
+//! The record is split into its `<e>` elements, which are then
+//! parsed on the rayon thread pool. The order of the events is
+//! the same as in the input.
+use std::io::Read;
+
+use rayon::prelude::*;
+
+use crate::parser::parse_event_with;
+use crate::version::FormatVersion;
+use crate::{Event, Eventrecord, EventrecordHeader, Location, ReadErr};
+
+/// Parse the header of a record and return a parallel iterator over its events
+pub fn par_events(
+    record: &str,
+) -> Result<
+    (
+        EventrecordHeader,
+        impl IndexedParallelIterator<Item = Result<Event, ReadErr>> + '_,
+    ),
+    ReadErr,
+> {
+    let mut reader = quick_xml::Reader::from_str(record);
+    let mut header = EventrecordHeader::read(&mut reader, &mut Vec::new())?;
+    let mut comments = Vec::new();
+    let events =
+        split_events(&record[reader.buffer_position()..], &mut comments)?;
+    header.version = FormatVersion::detect(comments);
+    let events = events.into_par_iter().enumerate().map(move |(n, e)| {
+        parse_event_with(e, false).map_err(|err| {
+            let offset = e.as_ptr() as usize - record.as_ptr() as usize;
+            let start = Location {
+                event: Some(n as u64),
+                ..Location::in_str(record, offset)
+            };
+            ReadErr::from(err.offset_by(&start))
+        })
+    });
+    Ok((header, events))
+}
+
+/// Parse a record, processing events in parallel
+pub fn par_from_str(record: &str) -> Result<Eventrecord, ReadErr> {
+    let (header, events) = par_events(record)?;
+    let events = events.collect::<Result<_, _>>()?;
+    let EventrecordHeader {
+        nevents,
+        nsubevents,
+        nreweights,
+        alpha_s_power,
+        name,
+        version,
+        extra,
+    } = header;
+    Ok(Eventrecord {
+        nevents,
+        nsubevents,
+        nreweights,
+        alpha_s_power,
+        name,
+        events,
+        version: Some(version),
+        extra,
+    })
+}
+
+/// Read a record, processing events in parallel
+///
+/// The whole input is read into memory before parsing.
+pub fn par_from_reader<R: Read>(mut reader: R) -> Result<Eventrecord, ReadErr> {
+    let mut record = String::new();
+    reader.read_to_string(&mut record)?;
+    par_from_str(&record)
+}
+
+// split the record body into the text of the individual `<e>` elements
+//
+// The comments preceding the first event are added to `comments`
+fn split_events<'a>(
+    body: &'a str,
+    comments: &mut Vec<&'a str>,
+) -> Result<Vec<&'a str>, ReadErr> {
+    use quick_xml::events::Event as XmlEvent;
+
+    let mut reader = quick_xml::Reader::from_str(body);
+    // the closing `</Eventrecord>` tag has no matching start tag here
+    reader.check_end_names(false);
+    let mut events = Vec::new();
+    // start of the current top-level element and its nesting depth
+    let mut start = 0;
+    let mut depth = 0;
+    loop {
+        let event = reader.read_event()?;
+        let end = reader.buffer_position();
+        match event {
+            XmlEvent::Eof => return Err(ReadErr::UnexpectedEof),
+            XmlEvent::Start(tag) => {
+                if depth == 0 {
+                    start = end - tag.len() - "<>".len();
+                }
+                depth += 1;
+            }
+            XmlEvent::End(tag)
+                if depth == 0
+                    && tag.local_name().as_ref() == b"Eventrecord" =>
+            {
+                return Ok(events);
+            }
+            XmlEvent::End(_) if depth == 0 => {}
+            XmlEvent::End(tag) => {
+                depth -= 1;
+                if depth == 0 && tag.local_name().as_ref() == b"e" {
+                    events.push(&body[start..end]);
+                }
+            }
+            XmlEvent::Empty(tag)
+                if depth == 0 && tag.local_name().as_ref() == b"e" =>
+            {
+                events.push(&body[end - tag.len() - "</>".len()..end]);
+            }
+            XmlEvent::Comment(comment) if depth == 0 && events.is_empty() => {
+                let start = end - comment.len() - "-->".len();
+                comments.push(&body[start..end - "-->".len()]);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::RECORD;
+    use crate::ParseErr;
+
+    #[test]
+    fn par_parse() {
+        let record = Eventrecord::from_reader(RECORD.as_bytes()).unwrap();
+        let par_record = par_from_str(RECORD).unwrap();
+        assert_eq!(par_record, record);
+        let par_record = par_from_reader(RECORD.as_bytes()).unwrap();
+        assert_eq!(par_record, record);
+    }
+
+    #[test]
+    fn par_parse_empty_event() {
+        let record = RECORD
+            .replacen("nevents=\"3\"", "nevents=\"5\"", 1)
+            .replacen("<e>", "<e/>\n<e />\n<e>", 1);
+        let serial: Vec<_> = crate::EventrecordReader::new(record.as_bytes())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(serial.len(), 5);
+        assert_eq!(serial[0], Event::default());
+        let (_, events) = par_events(&record).unwrap();
+        let parallel: Vec<_> = events.collect::<Result<_, _>>().unwrap();
+        assert_eq!(parallel, serial);
+    }
+
+    #[test]
+    fn par_parse_nested_end_tags() {
+        // `</e>` inside comments and CDATA does not end the event
+        let record = RECORD
+            .replacen("<se ", "<!-- </e> -->\n<se ", 1)
+            .replacen(
+                "</se>",
+                "<note><![CDATA[</e>]]><!-- </e> --></note>\n</se>",
+                1,
+            )
+            .replacen("</e>", "</e >", 2);
+        let serial = Eventrecord::from_reader(record.as_bytes()).unwrap();
+        assert_eq!(serial.events.len(), 3);
+        let parallel = par_from_str(&record).unwrap();
+        assert_eq!(parallel, serial);
+    }
+
+    #[test]
+    fn par_parse_unknown_version() {
+        let record = RECORD.replace("STRIPPER v0.1", "STRIPPER v0.2");
+        let serial = Eventrecord::from_reader(record.as_bytes()).unwrap();
+        let parallel = par_from_str(&record).unwrap();
+        assert_eq!(parallel, serial);
+        let version = FormatVersion::Unknown("0.2".to_owned());
+        assert_eq!(parallel.version, Some(version));
+    }
+
+    #[test]
+    fn par_parse_error_location() {
+        let broken = RECORD.replace("1610.067985,0,0,", "1610.067985,0,");
+        let Err(ReadErr::Parse(err)) = par_from_str(&broken) else {
+            panic!("expected parse error");
+        };
+        assert!(matches!(err.kind, ParseErr::NumEntries(_, 4)));
+        let location = err.location;
+        assert_eq!(location.event, Some(1));
+        assert_eq!(location.subevent, Some(1));
+        assert_eq!(location.line, Some(25));
+        assert_eq!(location.column, Some(13));
+        assert!(broken[location.offset as usize..].starts_with(" 1610.067985"));
+    }
+
+    #[test]
+    fn par_parse_truncated() {
+        let end = RECORD.rfind("</e>").unwrap();
+        let res = par_from_str(&RECORD[..end]);
+        assert!(matches!(res, Err(ReadErr::UnexpectedEof)));
+    }
+}
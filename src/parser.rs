@@ -0,0 +1,509 @@
+//! Hand-written parser for events
+//!
+//! The result is the same as deserialising with serde, but the
+//! parser works directly on the input slice instead of allocating a
+//! string for each attribute and each `<p>` or `<rw>` element.
+//!
+//! Errors are reported together with their [Location] in the input.
+use crate::{
+    parse_float, Event, ExtraContent, ExtraNode, Id, LocatedParseErr, Location,
+    ParseErr, Particle, Reweight, SubEvent,
+};
+
+/// Parse a single `<e>` element
+pub fn parse_event(input: &str) -> Result<Event, LocatedParseErr> {
+    parse_event_with(input, false)
+}
+
+// parse an `<e>` element, optionally keeping content outside the
+// STRIPPER format
+pub(crate) fn parse_event_with(
+    input: &str,
+    lossless: bool,
+) -> Result<Event, LocatedParseErr> {
+    let mut parser = Parser::new(input);
+    parser.lossless = lossless;
+    let res = parser.element("<e>", Parser::event);
+    res.map_err(|err| parser.locate(err))
+}
+
+/// Parse a single `<se>` element
+pub fn parse_subevent(input: &str) -> Result<SubEvent, LocatedParseErr> {
+    let mut parser = Parser::new(input);
+    let res = parser.element("<se>", Parser::subevent);
+    res.map(|(subevent, _)| subevent)
+        .map_err(|err| parser.locate(err))
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    rest: &'a str,
+    // the part of the input where the last error occurred
+    error_at: &'a str,
+    subevent: Option<usize>,
+    lossless: bool,
+    // comments not yet assigned to an element, only recorded in
+    // lossless mode
+    comments: Vec<&'a str>,
+}
+
+struct StartTag<'a> {
+    // the input starting with the tag
+    pos: &'a str,
+    name: &'a str,
+    attributes: &'a str,
+    is_empty: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            rest: input,
+            error_at: input,
+            subevent: None,
+            lossless: false,
+            comments: Vec::new(),
+        }
+    }
+
+    // parse the input, which has to consist of a single element
+    fn element<T>(
+        &mut self,
+        expected: &'static str,
+        parse: impl FnOnce(&mut Self, StartTag<'a>) -> Result<T, ParseErr>,
+    ) -> Result<T, ParseErr> {
+        self.skip_misc()?;
+        let tag = self.start_tag()?;
+        if tag.name != expected.trim_matches(&['<', '>'][..]) {
+            let err = ParseErr::Unexpected(expected, snippet(tag.name));
+            return self.at(tag.pos, Err(err));
+        }
+        let res = parse(self, tag)?;
+        self.end_of_input()?;
+        Ok(res)
+    }
+
+    fn locate(&self, err: ParseErr) -> LocatedParseErr {
+        let offset =
+            self.error_at.as_ptr() as usize - self.input.as_ptr() as usize;
+        let mut location = Location::in_str(self.input, offset);
+        location.subevent = self.subevent;
+        LocatedParseErr {
+            location,
+            kind: err,
+        }
+    }
+
+    // record `pos` as the error position if `res` is an error
+    fn at<T>(
+        &mut self,
+        pos: &'a str,
+        res: Result<T, ParseErr>,
+    ) -> Result<T, ParseErr> {
+        if res.is_err() {
+            self.error_at = pos;
+        }
+        res
+    }
+
+    fn event(&mut self, tag: StartTag<'a>) -> Result<Event, ParseErr> {
+        let mut event = Event::default();
+        if self.lossless {
+            for attr in Attributes(tag.attributes) {
+                let (key, value) = self.at(tag.attributes, attr)?;
+                let attr = (key.to_owned(), value.to_owned());
+                event.extra.event.attributes.push(attr);
+            }
+        }
+        if tag.is_empty {
+            return Ok(event);
+        }
+        loop {
+            let child = self.next_child("e")?;
+            let position = event.subevents.len();
+            let extra = &mut event.extra.event;
+            self.take_comments(extra, position);
+            let Some(tag) = child else {
+                break;
+            };
+            match tag.name {
+                "se" => {
+                    self.subevent = Some(position);
+                    let (subevent, extra) = self.subevent(tag)?;
+                    event.subevents.push(subevent);
+                    event.extra.set_subevent(position, extra);
+                    self.subevent = None;
+                }
+                _ => self.unknown_element(tag, extra, position)?,
+            }
+        }
+        Ok(event)
+    }
+
+    // parse an `<se>` element and the content outside the STRIPPER format
+    fn subevent(
+        &mut self,
+        tag: StartTag<'a>,
+    ) -> Result<(SubEvent, ExtraContent), ParseErr> {
+        let mut weight = None;
+        let mut mu_r = None;
+        let mut mu_f = None;
+        let mut extra = ExtraContent::default();
+        for attr in Attributes(tag.attributes) {
+            let (key, value) = self.at(tag.attributes, attr)?;
+            let entry = match key {
+                "w" => &mut weight,
+                "muR" => &mut mu_r,
+                "muF" => &mut mu_f,
+                _ => {
+                    if self.lossless {
+                        let attr = (key.to_owned(), value.to_owned());
+                        extra.attributes.push(attr);
+                    }
+                    continue;
+                }
+            };
+            *entry = Some(self.at(value, parse_float(value))?);
+        }
+        let missing = |name| ParseErr::MissingAttribute(name);
+        let mut subevent = SubEvent {
+            weight: self.at(tag.pos, weight.ok_or(missing("w")))?,
+            mu_r: self.at(tag.pos, mu_r.ok_or(missing("muR")))?,
+            mu_f: self.at(tag.pos, mu_f.ok_or(missing("muF")))?,
+            ..Default::default()
+        };
+        if tag.is_empty {
+            return Ok((subevent, extra));
+        }
+        loop {
+            let child = self.next_child("se")?;
+            let position = subevent.particles.len() + subevent.reweight.len();
+            self.take_comments(&mut extra, position);
+            let Some(tag) = child else {
+                break;
+            };
+            match tag.name {
+                "p" => subevent.particles.push(self.particle(tag)?),
+                "rw" => subevent.reweight.push(self.reweight(tag)?),
+                _ => self.unknown_element(tag, &mut extra, position)?,
+            }
+        }
+        Ok((subevent, extra))
+    }
+
+    // move the pending comments to `extra` at the given position
+    fn take_comments(&mut self, extra: &mut ExtraContent, position: usize) {
+        for comment in self.comments.drain(..) {
+            extra.push(position, ExtraNode::Comment(comment.to_owned()));
+        }
+    }
+
+    // skip an unknown element, keeping it in lossless mode
+    fn unknown_element(
+        &mut self,
+        tag: StartTag<'a>,
+        extra: &mut ExtraContent,
+        position: usize,
+    ) -> Result<(), ParseErr> {
+        let start = tag.pos;
+        let ncomments = self.comments.len();
+        self.skip_element(tag)?;
+        if self.lossless {
+            // comments inside the element are part of it
+            self.comments.truncate(ncomments);
+            let len = start.len() - self.rest.len();
+            let element = start[..len].to_owned();
+            extra.push(position, ExtraNode::Element(element));
+        }
+        Ok(())
+    }
+
+    fn particle(&mut self, tag: StartTag<'a>) -> Result<Particle, ParseErr> {
+        let id = self.attribute(&tag, "id")?;
+        let id: Id = self.at(id, id.parse())?;
+        let momentum = self.text_content(tag)?;
+        let momentum = self.at(momentum, momentum.parse())?;
+        Ok(Particle { id, momentum })
+    }
+
+    fn reweight(&mut self, tag: StartTag<'a>) -> Result<Reweight, ParseErr> {
+        let channel = self.attribute(&tag, "ch")?;
+        let parsed = channel
+            .trim()
+            .parse()
+            .map_err(|_| ParseErr::InvalidChannel(channel.to_owned()));
+        let channel = self.at(channel, parsed)?;
+        let reweights = self.text_content(tag)?;
+        let reweights = self.at(reweights, reweights.parse())?;
+        Ok(Reweight { channel, reweights })
+    }
+
+    // the value of a required attribute
+    fn attribute(
+        &mut self,
+        tag: &StartTag<'a>,
+        key: &'static str,
+    ) -> Result<&'a str, ParseErr> {
+        let value = Attributes(tag.attributes).get(key);
+        let value = self.at(tag.attributes, value)?;
+        self.at(tag.pos, value.ok_or(ParseErr::MissingAttribute(key)))
+    }
+
+    // Return the start tag of the next child element or `None` if
+    // the parent element ends
+    fn next_child(
+        &mut self,
+        parent: &str,
+    ) -> Result<Option<StartTag<'a>>, ParseErr> {
+        self.skip_misc()?;
+        if self.rest.starts_with("</") {
+            self.end_tag(parent)?;
+            Ok(None)
+        } else {
+            self.start_tag().map(Some)
+        }
+    }
+
+    fn start_tag(&mut self) -> Result<StartTag<'a>, ParseErr> {
+        let pos = self.rest;
+        let Some(rest) = self.rest.strip_prefix('<') else {
+            return Err(self.unexpected("start tag"));
+        };
+        let Some(end) = rest.find('>') else {
+            return Err(self.unexpected("start tag"));
+        };
+        let (tag, is_empty) = match rest[..end].strip_suffix('/') {
+            Some(tag) => (tag, true),
+            None => (&rest[..end], false),
+        };
+        let name_end = tag
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(tag.len());
+        let (name, attributes) = tag.split_at(name_end);
+        if name.is_empty() {
+            return Err(self.unexpected("start tag"));
+        }
+        self.rest = &rest[end + 1..];
+        Ok(StartTag {
+            pos,
+            name,
+            attributes,
+            is_empty,
+        })
+    }
+
+    fn end_tag(&mut self, name: &str) -> Result<(), ParseErr> {
+        let rest = self
+            .rest
+            .strip_prefix("</")
+            .and_then(|rest| rest.strip_prefix(name))
+            .map(|rest| rest.trim_start())
+            .and_then(|rest| rest.strip_prefix('>'));
+        let Some(rest) = rest else {
+            return Err(self.unexpected("end tag"));
+        };
+        self.rest = rest;
+        Ok(())
+    }
+
+    fn text_content(&mut self, tag: StartTag<'a>) -> Result<&'a str, ParseErr> {
+        if tag.is_empty {
+            return Ok("");
+        }
+        let end = self.rest.find('<').unwrap_or(self.rest.len());
+        let (text, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        self.end_tag(tag.name)?;
+        Ok(text)
+    }
+
+    fn skip_element(&mut self, tag: StartTag<'a>) -> Result<(), ParseErr> {
+        if tag.is_empty {
+            return Ok(());
+        }
+        let mut depth = 1;
+        while depth > 0 {
+            let Some(start) = self.rest.find('<') else {
+                return Err(self.unexpected("end tag"));
+            };
+            self.rest = &self.rest[start..];
+            self.skip_misc()?;
+            if let Some(rest) = self.rest.strip_prefix("<![CDATA[") {
+                let Some(end) = rest.find("]]>") else {
+                    return Err(self.unexpected("]]>"));
+                };
+                self.rest = &rest[end + "]]>".len()..];
+            } else if let Some(rest) = self.rest.strip_prefix("</") {
+                let Some(end) = rest.find('>') else {
+                    return Err(self.unexpected("end tag"));
+                };
+                self.rest = &rest[end + 1..];
+                depth -= 1;
+            } else if !self.start_tag()?.is_empty {
+                depth += 1;
+            }
+        }
+        Ok(())
+    }
+
+    // skip whitespace, comments, and processing instructions
+    fn skip_misc(&mut self) -> Result<(), ParseErr> {
+        loop {
+            self.rest = self.rest.trim_start();
+            let (rest, end) = if let Some(rest) = self.rest.strip_prefix("<!--")
+            {
+                (rest, "-->")
+            } else if let Some(rest) = self.rest.strip_prefix("<?") {
+                (rest, "?>")
+            } else {
+                return Ok(());
+            };
+            let Some(pos) = rest.find(end) else {
+                return Err(self.unexpected(end));
+            };
+            if self.lossless && end == "-->" {
+                self.comments.push(&rest[..pos]);
+            }
+            self.rest = &rest[pos + end.len()..];
+        }
+    }
+
+    fn end_of_input(&mut self) -> Result<(), ParseErr> {
+        self.skip_misc()?;
+        if self.rest.is_empty() {
+            Ok(())
+        } else {
+            Err(self.unexpected("end of input"))
+        }
+    }
+
+    fn unexpected(&mut self, expected: &'static str) -> ParseErr {
+        self.error_at = self.rest;
+        ParseErr::Unexpected(expected, snippet(self.rest))
+    }
+}
+
+// Iterator over `key="value"` pairs in a start tag
+struct Attributes<'a>(&'a str);
+
+impl<'a> Attributes<'a> {
+    fn get(self, key: &str) -> Result<Option<&'a str>, ParseErr> {
+        for attr in self {
+            let (k, value) = attr?;
+            if k == key {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<'a> Iterator for Attributes<'a> {
+    type Item = Result<(&'a str, &'a str), ParseErr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.0.trim_start();
+        if rest.is_empty() {
+            return None;
+        }
+        let Some((key, value)) = rest.split_once('=') else {
+            self.0 = "";
+            return Some(Err(ParseErr::Unexpected("attribute", snippet(rest))));
+        };
+        let value = value.trim_start();
+        let mut chars = value.chars();
+        let quote = chars.next();
+        let end = match quote {
+            Some(q @ ('"' | '\'')) => chars.as_str().find(q),
+            _ => None,
+        };
+        let Some(end) = end else {
+            self.0 = "";
+            return Some(Err(ParseErr::Unexpected(
+                "quoted attribute value",
+                snippet(value),
+            )));
+        };
+        self.0 = &value[end + 2..];
+        Some(Ok((key.trim_end(), &value[1..end + 1])))
+    }
+}
+
+fn snippet(s: &str) -> String {
+    const MAX_LEN: usize = 20;
+    s.chars().take(MAX_LEN).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::SUBEVENT;
+
+    #[test]
+    fn parse_subevent_agrees() {
+        let subevent: SubEvent = quick_xml::de::from_str(SUBEVENT).unwrap();
+        assert_eq!(parse_subevent(SUBEVENT).unwrap(), subevent);
+    }
+
+    #[test]
+    fn parse_event_agrees() {
+        let txt = format!(
+            "<e>\n<!-- comment -->\n{SUBEVENT}\n{SUBEVENT}\n<unknown a='1'><x/></unknown>\n</e>\n"
+        );
+        let event: Event = quick_xml::de::from_str(&txt).unwrap();
+        assert_eq!(event.subevents.len(), 2);
+        assert_eq!(parse_event(&txt).unwrap(), event);
+        assert_eq!(parse_event("<e/>").unwrap(), Event::default());
+    }
+
+    fn parse_err(input: &str) -> ParseErr {
+        parse_subevent(input).unwrap_err().kind
+    }
+
+    #[test]
+    fn parse_errors() {
+        let no_weight = SUBEVENT.replace("w=\"-0.0002369763508\"", "");
+        assert!(matches!(
+            parse_err(&no_weight),
+            ParseErr::MissingAttribute("w")
+        ));
+        let bad_momentum =
+            SUBEVENT.replace("5780.608219,0,0,", "5780.608219,0,0");
+        assert!(matches!(
+            parse_err(&bad_momentum),
+            ParseErr::NumEntries(_, 4)
+        ));
+        let bad_float = SUBEVENT.replace("334.3891359", "3x4");
+        assert!(matches!(parse_err(&bad_float), ParseErr::InvalidFloat(_)));
+        let bad_status = SUBEVENT.replace("\"0,6\"", "\"2,6\"");
+        assert!(matches!(parse_err(&bad_status), ParseErr::InvalidStatus(_)));
+        let bad_pdg_id = SUBEVENT.replace("\"0,6\"", "\"0,x\"");
+        assert!(matches!(
+            parse_err(&bad_pdg_id),
+            ParseErr::InvalidPdgCode(_)
+        ));
+        let truncated = &SUBEVENT[..SUBEVENT.len() - 3];
+        assert!(matches!(parse_err(truncated), ParseErr::Unexpected(..)));
+    }
+
+    #[test]
+    fn error_location() {
+        let txt = format!("<e>\n{SUBEVENT}\n{SUBEVENT}\n</e>");
+        let pos = txt.rfind("\"0,6\"").unwrap();
+        let broken = format!("{}\"2,6\"{}", &txt[..pos], &txt[pos + 5..]);
+        let err = parse_event(&broken).unwrap_err();
+        assert!(matches!(err.kind, ParseErr::InvalidStatus(_)));
+        let location = err.location;
+        assert_eq!(location.event, None);
+        assert_eq!(location.subevent, Some(1));
+        assert_eq!(location.offset, pos as u64 + 1);
+        assert_eq!(location.line, Some(12));
+        assert_eq!(location.column, Some(8));
+
+        let no_channel = SUBEVENT.replace(" ch=\"12\"", "");
+        let location = parse_subevent(&no_channel).unwrap_err().location;
+        assert_eq!(location.subevent, None);
+        assert_eq!((location.line, location.column), (Some(6), Some(1)));
+    }
+}
@@ -0,0 +1,578 @@
+//! PDF uncertainties
+//!
+//! To estimate the PDF uncertainty of an observable, the events are
+//! reweighted to every member of a PDF set, see
+//! [PdfReweighter::member_weights]. The values of the observable for
+//! the individual members are then combined according to the
+//! [ErrorType] of the set. The [ErrorSet] describing this combination
+//! is taken from the metadata of the set, following the LHAPDF
+//! conventions:
+//!
+//! - `ErrorType`: `replicas`, `hessian`, or `symmhessian`, optionally
+//!   followed by parameter variations, e.g. `hessian+as`. Each
+//!   parameter variation adds two members at the end of the set, which
+//!   do not contribute to the PDF uncertainty.
+//! - `ErrorConfLevel`: the confidence level of the uncertainty in
+//!   percent, by default one standard deviation.
+//!
+//! The uncertainty can be rescaled to a different confidence level,
+//! assuming a Gaussian distribution.
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::lhapdf::{Info, LhapdfErr};
+use crate::reweight::{PdfProvider, PdfReweighter, ReweightErr};
+use crate::{Event, SubEvent};
+
+/// Confidence level of one standard deviation in percent
+pub const ONE_SIGMA: f64 = 68.268_949_213_708_58;
+
+impl PdfReweighter<'_> {
+    /// The weights of a subevent for each member of a PDF set
+    pub fn member_weights<P: PdfProvider>(
+        &self,
+        subevent: &SubEvent,
+        members: &[P],
+    ) -> Result<Vec<f64>, ReweightErr> {
+        members
+            .iter()
+            .map(|pdf| self.subevent_weight(subevent, pdf))
+            .collect()
+    }
+
+    /// The weights of an event for each member of a PDF set
+    ///
+    /// Each weight is the sum of the subevent weights for the member.
+    pub fn event_member_weights<P: PdfProvider>(
+        &self,
+        event: &Event,
+        members: &[P],
+    ) -> Result<Vec<f64>, ReweightErr> {
+        let mut weights = vec![0.; members.len()];
+        for subevent in &event.subevents {
+            let member_weights = self.member_weights(subevent, members)?;
+            for (weight, member) in weights.iter_mut().zip(member_weights) {
+                *weight += member;
+            }
+        }
+        Ok(weights)
+    }
+}
+
+/// How the members of a PDF set describe its uncertainty
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum ErrorType {
+    /// Monte Carlo replicas
+    Replicas,
+    /// Pairs of asymmetric Hessian eigenvector variations
+    Hessian,
+    /// Symmetric Hessian eigenvector variations
+    SymmHessian,
+}
+
+impl FromStr for ErrorType {
+    type Err = UncertaintyErr;
+
+    fn from_str(error_type: &str) -> Result<Self, Self::Err> {
+        match error_type {
+            "replicas" => Ok(Self::Replicas),
+            "hessian" => Ok(Self::Hessian),
+            "symmhessian" => Ok(Self::SymmHessian),
+            _ => Err(UncertaintyErr::UnknownErrorType(error_type.to_owned())),
+        }
+    }
+}
+
+/// Description of the uncertainty of a PDF set
+///
+/// The number of members and the confidence level are checked for
+/// consistency with the error type before combining any values.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct ErrorSet {
+    pub error_type: ErrorType,
+    /// Confidence level in percent
+    pub conf_level: f64,
+    /// Total number of members, including the central one
+    pub num_members: usize,
+    /// Number of members describing the PDF uncertainty
+    pub num_error_members: usize,
+}
+
+impl ErrorSet {
+    /// Read the description from the metadata of a PDF set
+    pub fn from_info(info: &Info) -> Result<Self, UncertaintyErr> {
+        let num_members: usize = info.parse_value("NumMembers")?;
+        let error_type = info
+            .get("ErrorType")
+            .ok_or_else(|| LhapdfErr::MissingKey("ErrorType".to_owned()))?;
+        let mut parts = error_type.split('+');
+        let error_type: ErrorType = parts.next().unwrap_or_default().parse()?;
+        let num_param_members = 2 * parts.count();
+        let conf_level = match info.get("ErrorConfLevel") {
+            Some(_) => info.parse_value("ErrorConfLevel")?,
+            None => ONE_SIGMA,
+        };
+        let num_error_members = num_members
+            .checked_sub(1 + num_param_members)
+            .ok_or(UncertaintyErr::InvalidNumMembers(num_members))?;
+        let errors = Self {
+            error_type,
+            conf_level,
+            num_members,
+            num_error_members,
+        };
+        errors.check()?;
+        Ok(errors)
+    }
+
+    // check that the number of members and the confidence level are
+    // consistent with the error type
+    fn check(&self) -> Result<(), UncertaintyErr> {
+        if !(self.conf_level > 0. && self.conf_level < 100.) {
+            return Err(UncertaintyErr::InvalidConfLevel(self.conf_level));
+        }
+        let n = self.num_error_members;
+        let valid = n < self.num_members
+            && match self.error_type {
+                ErrorType::Replicas => n >= 2,
+                ErrorType::Hessian => n.is_multiple_of(2),
+                ErrorType::SymmHessian => true,
+            };
+        if !valid {
+            return Err(UncertaintyErr::InvalidNumMembers(self.num_members));
+        }
+        Ok(())
+    }
+
+    /// Combine the values of an observable for all members
+    ///
+    /// If a confidence level `cl` in percent is given, the uncertainty
+    /// is rescaled to it from the confidence level of the set.
+    pub fn uncertainty(
+        &self,
+        values: &[f64],
+        cl: Option<f64>,
+    ) -> Result<PdfUncertainty, UncertaintyErr> {
+        self.check()?;
+        if values.len() != self.num_members {
+            return Err(UncertaintyErr::NumValues {
+                expected: self.num_members,
+                actual: values.len(),
+            });
+        }
+        let errors = &values[1..=self.num_error_members];
+        let mut res = match self.error_type {
+            ErrorType::Replicas => {
+                let n = errors.len() as f64;
+                let mean = errors.iter().sum::<f64>() / n;
+                let var =
+                    errors.iter().map(|v| (v - mean).powi(2)).sum::<f64>()
+                        / (n - 1.);
+                PdfUncertainty::symmetric(mean, var.sqrt())
+            }
+            ErrorType::SymmHessian => {
+                let central = values[0];
+                let err = errors.iter().map(|v| (v - central).powi(2)).sum();
+                PdfUncertainty::symmetric(central, f64::sqrt(err))
+            }
+            ErrorType::Hessian => {
+                let central = values[0];
+                let (mut plus, mut minus, mut symm) = (0., 0., 0.);
+                for pair in errors.chunks_exact(2) {
+                    let (up, down) = (pair[0] - central, pair[1] - central);
+                    plus += up.max(down).max(0.).powi(2);
+                    minus += (-up).max(-down).max(0.).powi(2);
+                    symm += (up - down).powi(2);
+                }
+                PdfUncertainty {
+                    central,
+                    err_plus: f64::sqrt(plus),
+                    err_minus: f64::sqrt(minus),
+                    err_symm: f64::sqrt(symm) / 2.,
+                }
+            }
+        };
+        if let Some(cl) = cl {
+            let scale =
+                gaussian_quantile(cl)? / gaussian_quantile(self.conf_level)?;
+            res.err_plus *= scale;
+            res.err_minus *= scale;
+            res.err_symm *= scale;
+        }
+        Ok(res)
+    }
+
+    /// Combine histograms filled for each member
+    ///
+    /// `histograms` contains the bin contents obtained for each member.
+    /// The result contains the uncertainty for each bin. All histograms
+    /// must have the same number of bins.
+    pub fn bins<H: AsRef<[f64]>>(
+        &self,
+        histograms: &[H],
+        cl: Option<f64>,
+    ) -> Result<Vec<PdfUncertainty>, UncertaintyErr> {
+        if histograms.len() != self.num_members {
+            return Err(UncertaintyErr::NumValues {
+                expected: self.num_members,
+                actual: histograms.len(),
+            });
+        }
+        let nbins = histograms[0].as_ref().len();
+        if let Some(h) = histograms.iter().find(|h| h.as_ref().len() != nbins) {
+            return Err(UncertaintyErr::NumBins {
+                expected: nbins,
+                actual: h.as_ref().len(),
+            });
+        }
+        (0..nbins)
+            .map(|bin| {
+                let values: Vec<_> =
+                    histograms.iter().map(|h| h.as_ref()[bin]).collect();
+                self.uncertainty(&values, cl)
+            })
+            .collect()
+    }
+}
+
+/// Central value and PDF uncertainty of an observable
+#[derive(Copy, Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct PdfUncertainty {
+    pub central: f64,
+    /// Upward uncertainty
+    pub err_plus: f64,
+    /// Downward uncertainty
+    pub err_minus: f64,
+    /// Symmetrised uncertainty
+    pub err_symm: f64,
+}
+
+impl PdfUncertainty {
+    fn symmetric(central: f64, err: f64) -> Self {
+        Self {
+            central,
+            err_plus: err,
+            err_minus: err,
+            err_symm: err,
+        }
+    }
+}
+
+// the number of standard deviations for a two-sided confidence level
+// in percent
+fn gaussian_quantile(cl: f64) -> Result<f64, UncertaintyErr> {
+    if !(cl > 0. && cl < 100.) {
+        return Err(UncertaintyErr::InvalidConfLevel(cl));
+    }
+    // the quantile of the standard normal distribution at 1/2 + q,
+    // M. J. Wichura, Algorithm AS 241, Appl. Statist. 37 (1988) 477,
+    // with a relative accuracy of about 1e-16
+    const A: [f64; 8] = [
+        3.387_132_872_796_366_6,
+        1.331_416_678_917_843_8e2,
+        1.971_590_950_306_551_4e3,
+        1.373_169_376_550_946e4,
+        4.592_195_393_154_987e4,
+        6.726_577_092_700_87e4,
+        3.343_057_558_358_813e4,
+        2.509_080_928_730_122_7e3,
+    ];
+    const B: [f64; 8] = [
+        1.,
+        4.231_333_070_160_091e1,
+        6.871_870_074_920_579e2,
+        5.394_196_021_424_751e3,
+        2.121_379_430_158_659_7e4,
+        3.930_789_580_009_271e4,
+        2.872_908_573_572_194_3e4,
+        5.226_495_278_852_854e3,
+    ];
+    const C: [f64; 8] = [
+        1.423_437_110_749_683_6,
+        4.630_337_846_156_545,
+        5.769_497_221_460_691,
+        3.647_848_324_763_204_5,
+        1.270_458_252_452_368_4,
+        2.417_807_251_774_506e-1,
+        2.272_384_498_926_918_4e-2,
+        7.745_450_142_783_414e-4,
+    ];
+    const D: [f64; 8] = [
+        1.,
+        2.053_191_626_637_759,
+        1.676_384_830_183_803_8,
+        6.897_673_349_851e-1,
+        1.481_039_764_274_800_7e-1,
+        1.519_866_656_361_645_7e-2,
+        5.475_938_084_995_345e-4,
+        1.050_750_071_644_416_8e-9,
+    ];
+    const E: [f64; 8] = [
+        6.657_904_643_501_103,
+        5.463_784_911_164_114,
+        1.784_826_539_917_291_3,
+        2.965_605_718_285_048_7e-1,
+        2.653_218_952_657_612_4e-2,
+        1.242_660_947_388_078_4e-3,
+        2.711_555_568_743_487_6e-5,
+        2.010_334_399_292_288_1e-7,
+    ];
+    const F: [f64; 8] = [
+        1.,
+        5.998_322_065_558_879e-1,
+        1.369_298_809_227_358e-1,
+        1.487_536_129_085_061_5e-2,
+        7.868_691_311_456_133e-4,
+        1.846_318_317_510_054_8e-5,
+        1.421_511_758_316_446e-7,
+        2.044_263_103_389_939_7e-15,
+    ];
+    let poly = |coeff: &[f64; 8], x: f64| {
+        coeff.iter().rev().fold(0., |acc, c| acc * x + c)
+    };
+    let q = cl / 200.;
+    if q <= 0.425 {
+        let r = 0.180625 - q * q;
+        return Ok(q * poly(&A, r) / poly(&B, r));
+    }
+    // compute the tail probability directly to avoid cancellations
+    let r = f64::sqrt(-f64::ln((100. - cl) / 200.));
+    let z = if r <= 5. {
+        let r = r - 1.6;
+        poly(&C, r) / poly(&D, r)
+    } else {
+        let r = r - 5.;
+        poly(&E, r) / poly(&F, r)
+    };
+    Ok(z)
+}
+
+#[derive(Debug, Error)]
+pub enum UncertaintyErr {
+    #[error("Unknown PDF error type '{0}'")]
+    UnknownErrorType(String),
+    #[error("Invalid number of PDF members: {0}")]
+    InvalidNumMembers(usize),
+    #[error("Expected values for {expected} PDF members, found {actual}")]
+    NumValues { expected: usize, actual: usize },
+    #[error("Invalid confidence level: {0}%")]
+    InvalidConfLevel(f64),
+    #[error("Expected {expected} bins, found {actual}")]
+    NumBins { expected: usize, actual: usize },
+    #[error("Invalid PDF set metadata")]
+    Metadata(#[from] LhapdfErr),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alpha_s::AlphaS;
+    use crate::channels::Init;
+    use crate::lhapdf::PdfSet;
+    use crate::test_util::{rel_diff, INIT};
+
+    const TOY_HESSIAN: &str =
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/lhapdf/ToyHessian");
+
+    fn reweight(channel: u32) -> crate::Reweight {
+        crate::Reweight {
+            channel,
+            reweights: crate::Reweights {
+                x1: 0.05,
+                x2: 0.2,
+                log_coeff: vec![1e-3],
+            },
+        }
+    }
+
+    #[test]
+    fn member_weights() {
+        let init: Init = quick_xml::de::from_str(INIT).unwrap();
+        let set = PdfSet::open(TOY_HESSIAN).unwrap();
+        let members = set.members().unwrap();
+        assert_eq!(members.len(), 3);
+        let alpha_s = AlphaS::new(0.118);
+        let reweighter =
+            PdfReweighter::new(&init, 2, &members[0]).fallback_alpha_s(alpha_s);
+        let gg = SubEvent {
+            mu_r: 50.,
+            mu_f: 50.,
+            reweight: vec![reweight(0)],
+            ..Default::default()
+        };
+        let qqbar = SubEvent {
+            reweight: vec![reweight(1)],
+            ..gg.clone()
+        };
+
+        // only the gluon density differs between the members, and the
+        // central member is the original PDF
+        let gg_weights = reweighter.member_weights(&gg, &members).unwrap();
+        for (weight, factor) in gg_weights.iter().zip([1., 1.1, 0.95]) {
+            let expected = 1e-3 * factor * factor;
+            assert!(rel_diff(*weight, expected) < 1e-7);
+        }
+        let qqbar_weights =
+            reweighter.member_weights(&qqbar, &members).unwrap();
+        assert!(qqbar_weights.iter().all(|w| rel_diff(*w, 1e-3) < 1e-12));
+
+        let event = Event {
+            subevents: vec![gg, qqbar],
+            ..Default::default()
+        };
+        let weights =
+            reweighter.event_member_weights(&event, &members).unwrap();
+        for n in 0..3 {
+            let expected = gg_weights[n] + qqbar_weights[n];
+            assert!(rel_diff(weights[n], expected) < 1e-12);
+        }
+
+        let errors = ErrorSet::from_info(set.info()).unwrap();
+        assert_eq!(errors.error_type, ErrorType::Hessian);
+        assert_eq!(errors.conf_level, 90.);
+        let unc = errors.uncertainty(&weights, None).unwrap();
+        assert_eq!(unc.central, weights[0]);
+        assert!(rel_diff(unc.err_plus, weights[1] - weights[0]) < 1e-12);
+        assert!(rel_diff(unc.err_minus, weights[0] - weights[2]) < 1e-12);
+    }
+
+    #[test]
+    fn combine() {
+        let info = Info::parse("NumMembers: 5\nErrorType: hessian+as\n");
+        let errors = ErrorSet::from_info(&info).unwrap();
+        assert_eq!(errors.num_error_members, 2);
+        assert_eq!(errors.conf_level, ONE_SIGMA);
+        // the alpha_s variations in the last two members are ignored
+        let values = [1., 1.2, 0.7, 5., -5.];
+        let unc = errors.uncertainty(&values, None).unwrap();
+        assert_eq!(unc.central, 1.);
+        assert!((unc.err_plus - 0.2).abs() < 1e-12);
+        assert!((unc.err_minus - 0.3).abs() < 1e-12);
+        assert!((unc.err_symm - 0.25).abs() < 1e-12);
+        // rescale from one standard deviation to 90% CL
+        let unc90 = errors.uncertainty(&values, Some(90.)).unwrap();
+        assert!(
+            rel_diff(unc90.err_plus / unc.err_plus, 1.6448536269514722) < 1e-15
+        );
+        for cl in [0., 100., -5., 120., f64::NAN] {
+            assert!(matches!(
+                errors.uncertainty(&values, Some(cl)),
+                Err(UncertaintyErr::InvalidConfLevel(_))
+            ));
+        }
+        assert!(matches!(
+            errors.uncertainty(&values[..3], None),
+            Err(UncertaintyErr::NumValues {
+                expected: 5,
+                actual: 3
+            })
+        ));
+
+        let info = Info::parse("NumMembers: 3\nErrorType: symmhessian\n");
+        let errors = ErrorSet::from_info(&info).unwrap();
+        let unc = errors.uncertainty(&[1., 1.3, 0.6], None).unwrap();
+        assert!((unc.err_symm - 0.5).abs() < 1e-12);
+        assert_eq!(unc.err_plus, unc.err_minus);
+
+        let info = Info::parse(
+            "NumMembers: 5\nErrorType: replicas\nErrorConfLevel: 68.268949",
+        );
+        let errors = ErrorSet::from_info(&info).unwrap();
+        let histograms = [[0., 1.], [2., 1.], [3., 2.], [1., 3.], [2., 4.]];
+        let bins = errors.bins(&histograms, None).unwrap();
+        assert_eq!(bins.len(), 2);
+        assert_eq!(bins[0].central, 2.);
+        assert!((bins[0].err_symm - f64::sqrt(2. / 3.)).abs() < 1e-12);
+        assert_eq!(bins[1].central, 2.5);
+        assert!((bins[1].err_symm - f64::sqrt(5. / 3.)).abs() < 1e-12);
+        let ragged: [&[f64]; 5] =
+            [&[0., 1.], &[2., 1.], &[3.], &[1., 3.], &[2.]];
+        assert!(matches!(
+            errors.bins(&ragged, None),
+            Err(UncertaintyErr::NumBins {
+                expected: 2,
+                actual: 1
+            })
+        ));
+        assert!(matches!(
+            errors.bins(&histograms[..4], None),
+            Err(UncertaintyErr::NumValues {
+                expected: 5,
+                actual: 4
+            })
+        ));
+
+        for invalid in [
+            "NumMembers: 4\nErrorType: hessian\n",
+            "NumMembers: 2\nErrorType: replicas\n",
+            "NumMembers: 1\nErrorType: hessian+as\n",
+        ] {
+            assert!(matches!(
+                ErrorSet::from_info(&Info::parse(invalid)),
+                Err(UncertaintyErr::InvalidNumMembers(_))
+            ));
+        }
+        assert!(matches!(
+            ErrorSet::from_info(&Info::parse(
+                "NumMembers: 3\nErrorType: symmhessian\nErrorConfLevel: 100"
+            )),
+            Err(UncertaintyErr::InvalidConfLevel(_))
+        ));
+        assert!(matches!(
+            ErrorSet::from_info(&Info::parse(
+                "NumMembers: 3\nErrorType: bootstrap"
+            )),
+            Err(UncertaintyErr::UnknownErrorType(_))
+        ));
+
+        // sets built by hand are checked as well
+        let errors = ErrorSet::from_info(&Info::parse(
+            "NumMembers: 5\nErrorType: hessian\n",
+        ))
+        .unwrap();
+        let values = [1.; 5];
+        for invalid in [
+            ErrorSet {
+                num_error_members: 5,
+                ..errors
+            },
+            ErrorSet {
+                num_error_members: 3,
+                ..errors
+            },
+            ErrorSet {
+                error_type: ErrorType::Replicas,
+                num_error_members: 1,
+                ..errors
+            },
+        ] {
+            assert!(matches!(
+                invalid.uncertainty(&values, None),
+                Err(UncertaintyErr::InvalidNumMembers(5))
+            ));
+        }
+        let invalid = ErrorSet {
+            conf_level: 0.,
+            ..errors
+        };
+        assert!(matches!(
+            invalid.uncertainty(&values, None),
+            Err(UncertaintyErr::InvalidConfLevel(_))
+        ));
+    }
+
+    #[test]
+    fn quantile() {
+        // reference values of the normal distribution
+        for (cl, z) in [
+            (ONE_SIGMA, 1.),
+            (90., 1.6448536269514722),
+            (95., 1.959963984540054),
+            (99., 2.5758293035489004),
+            (99.9999, 4.891638475698358),
+        ] {
+            assert!(rel_diff(gaussian_quantile(cl).unwrap(), z) < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,533 @@
+//! Recompute event weights for a different PDF set
+//!
+//! Each `<rw>` entry of a subevent stores the momentum fractions `x1`
+//! and `x2` of the incoming partons together with coefficients of the
+//! logarithms listed in [Contribution::rw]. The coefficients include
+//! the parton luminosity and the strong coupling of the PDF set in
+//! [Init::incoming] at the scales of the subevent: without logarithms,
+//! the coefficient of each entry is its contribution to the subevent
+//! weight. The contribution of a reweighting entry for new scales
+//! `muR`, `muF` and new PDFs is therefore
+//!
+//! ```text
+//! (c_0 + c_1 l_1 + c_2 l_2 + ...) * L(x1, x2, muF) * alpha_s(muR)^p
+//!     / (L_0(x1, x2, muF_0) * alpha_s_0(muR_0)^p)
+//! ```
+//!
+//! where `L` is the sum of `f_a(x1, muF) f_b(x2, muF)` over the parton
+//! pairs `(a, b)` of the [Channel], `p` is
+//! [Eventrecord::alpha_s_power], and the `l_i` are logarithms of the
+//! scales, e.g. `log(muR**2)`. `L_0` and `alpha_s_0` are taken from
+//! the original PDF set at the scales `muR_0` and `muF_0` stored in
+//! the subevent.
+//!
+//! The parton densities and the strong coupling are taken from a
+//! [PdfProvider]. If the provider has no strong coupling, a
+//! [fallback](PdfReweighter::fallback_alpha_s) can be used instead.
+//!
+//! [Init::incoming]: crate::channels::Init::incoming
+//! [Contribution::rw]: crate::normalization::Contribution::rw
+//! [Channel]: crate::channels::Channel
+//! [Eventrecord::alpha_s_power]: crate::Eventrecord::alpha_s_power
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use particle_id::ParticleID;
+use thiserror::Error;
+
+use crate::alpha_s::AlphaS;
+use crate::channels::{Channel, Init};
+use crate::normalization;
+use crate::{Event, SubEvent};
+
+/// Source of parton densities and the strong coupling
+pub trait PdfProvider {
+    /// The momentum density `x f(x, Q^2)` of the given parton
+    fn xfx_q2(&self, id: ParticleID, x: f64, q2: f64) -> f64;
+
+    /// The strong coupling at the scale `Q^2`, if available
+    fn alpha_s_q2(&self, q2: f64) -> Option<f64>;
+}
+
+impl<P: PdfProvider + ?Sized> PdfProvider for &P {
+    fn xfx_q2(&self, id: ParticleID, x: f64, q2: f64) -> f64 {
+        (**self).xfx_q2(id, x, q2)
+    }
+
+    fn alpha_s_q2(&self, q2: f64) -> Option<f64> {
+        (**self).alpha_s_q2(q2)
+    }
+}
+
+/// Logarithm multiplying a coefficient in a reweighting entry
+///
+/// The text representation is `log(muR**2)` or `log(muF**2)`,
+/// optionally raised to a power, e.g. `log(muR**2)**2`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct LogTerm {
+    pub scale: LogScale,
+    pub power: u32,
+}
+
+/// The scale in a [LogTerm]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum LogScale {
+    /// Renormalisation scale
+    MuR,
+    /// Factorisation scale
+    MuF,
+}
+
+impl LogTerm {
+    /// The value of the logarithm for the given scales
+    pub fn eval(&self, mu_r: f64, mu_f: f64) -> f64 {
+        let mu = match self.scale {
+            LogScale::MuR => mu_r,
+            LogScale::MuF => mu_f,
+        };
+        (mu * mu).ln().powi(self.power as i32)
+    }
+}
+
+impl FromStr for LogTerm {
+    type Err = ReweightErr;
+
+    fn from_str(term: &str) -> Result<Self, Self::Err> {
+        let invalid = || ReweightErr::InvalidLogTerm(term.to_owned());
+        let trimmed = term.trim();
+        let (scale, rest) =
+            if let Some(rest) = trimmed.strip_prefix("log(muR**2)") {
+                (LogScale::MuR, rest)
+            } else if let Some(rest) = trimmed.strip_prefix("log(muF**2)") {
+                (LogScale::MuF, rest)
+            } else {
+                return Err(invalid());
+            };
+        let power = match rest.strip_prefix("**") {
+            Some(power) => power.parse().map_err(|_| invalid())?,
+            None if rest.is_empty() => 1,
+            None => return Err(invalid()),
+        };
+        Ok(Self { scale, power })
+    }
+}
+
+impl Display for LogTerm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scale = match self.scale {
+            LogScale::MuR => "muR",
+            LogScale::MuF => "muF",
+        };
+        write!(f, "log({scale}**2)")?;
+        if self.power != 1 {
+            write!(f, "**{}", self.power)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse the logarithms listed in a [Contribution::rw](crate::normalization::Contribution::rw)
+pub fn log_terms(
+    rw: &normalization::Reweight,
+) -> Result<Vec<LogTerm>, ReweightErr> {
+    rw.rwentry
+        .iter()
+        .filter(|e| !matches!(e.trim(), "x1" | "x2"))
+        .map(|e| e.parse())
+        .collect()
+}
+
+/// Recompute event weights with a [PdfProvider]
+#[derive(Clone)]
+pub struct PdfReweighter<'a> {
+    init: &'a Init,
+    alpha_s_power: u64,
+    original: &'a dyn PdfProvider,
+    logs: Vec<LogTerm>,
+    fallback_alpha_s: Option<AlphaS>,
+}
+
+impl<'a> PdfReweighter<'a> {
+    /// Reweight events with the channels in `init`
+    ///
+    /// `alpha_s_power` is the power of the strong coupling given by
+    /// [Eventrecord::alpha_s_power](crate::Eventrecord::alpha_s_power).
+    /// `original` has to provide the PDF set the record was generated
+    /// with, i.e. the member given by [Init::incoming]. By default,
+    /// reweighting entries are expected to have only a constant
+    /// coefficient.
+    pub fn new(
+        init: &'a Init,
+        alpha_s_power: u64,
+        original: &'a dyn PdfProvider,
+    ) -> Self {
+        Self {
+            init,
+            alpha_s_power,
+            original,
+            logs: Vec::new(),
+            fallback_alpha_s: None,
+        }
+    }
+
+    /// Use the logarithms listed in a [Contribution::rw](crate::normalization::Contribution::rw)
+    pub fn logs(
+        mut self,
+        rw: &normalization::Reweight,
+    ) -> Result<Self, ReweightErr> {
+        self.logs = log_terms(rw)?;
+        Ok(self)
+    }
+
+    /// Use the given strong coupling if a PDF provider has none
+    ///
+    /// The fallback applies to both the original and the new PDFs.
+    pub fn fallback_alpha_s(mut self, alpha_s: AlphaS) -> Self {
+        self.fallback_alpha_s = Some(alpha_s);
+        self
+    }
+
+    /// The weight of a subevent for the given PDFs
+    pub fn subevent_weight(
+        &self,
+        subevent: &SubEvent,
+        pdf: &impl PdfProvider,
+    ) -> Result<f64, ReweightErr> {
+        self.weight_at(subevent, pdf, subevent.mu_r, subevent.mu_f)
+    }
+
+    /// The weight of an event for the given PDFs
+    ///
+    /// This is the sum of the subevent weights.
+    pub fn event_weight(
+        &self,
+        event: &Event,
+        pdf: &impl PdfProvider,
+    ) -> Result<f64, ReweightErr> {
+        event
+            .subevents
+            .iter()
+            .map(|s| self.subevent_weight(s, pdf))
+            .sum()
+    }
+
+    /// Replace the subevent weights by the ones for the given PDFs
+    pub fn reweight(
+        &self,
+        event: &mut Event,
+        pdf: &impl PdfProvider,
+    ) -> Result<(), ReweightErr> {
+        for subevent in &mut event.subevents {
+            subevent.weight = self.subevent_weight(subevent, pdf)?;
+        }
+        Ok(())
+    }
+
+    // the weight of a subevent with the given scales
+    pub(crate) fn weight_at(
+        &self,
+        subevent: &SubEvent,
+        pdf: &impl PdfProvider,
+        mu_r: f64,
+        mu_f: f64,
+    ) -> Result<f64, ReweightErr> {
+        let coupling = self.coupling(pdf, mu_r)?
+            / self.coupling(self.original, subevent.mu_r)?;
+        let mut weight = 0.;
+        for rw in &subevent.reweight {
+            let channel = self
+                .init
+                .channels
+                .get(rw.channel)
+                .ok_or(ReweightErr::UnknownChannel(rw.channel))?;
+            let coeff = &rw.reweights.log_coeff;
+            if coeff.len() != self.logs.len() + 1 {
+                return Err(ReweightErr::NumLogCoeff {
+                    expected: self.logs.len() + 1,
+                    actual: coeff.len(),
+                });
+            }
+            let logs = self.logs.iter().map(|l| l.eval(mu_r, mu_f));
+            let poly = coeff[0]
+                + coeff[1..].iter().zip(logs).map(|(c, l)| c * l).sum::<f64>();
+            let (x1, x2) = (rw.reweights.x1, rw.reweights.x2);
+            let original =
+                self.lumi(self.original, channel, x1, x2, subevent.mu_f);
+            // the coefficients vanish if the original luminosity does
+            if original == 0. {
+                continue;
+            }
+            let lumi = self.lumi(pdf, channel, x1, x2, mu_f);
+            weight += poly * lumi / original * coupling;
+        }
+        Ok(weight)
+    }
+
+    // the power of the strong coupling at the scale `mu_r`
+    fn coupling(
+        &self,
+        pdf: &(impl PdfProvider + ?Sized),
+        mu_r: f64,
+    ) -> Result<f64, ReweightErr> {
+        if self.alpha_s_power == 0 {
+            return Ok(1.);
+        }
+        let q2 = mu_r * mu_r;
+        let alpha_s = pdf
+            .alpha_s_q2(q2)
+            .or_else(|| Some(self.fallback_alpha_s?.alpha_s_q2(q2)))
+            .ok_or(ReweightErr::NoAlphaS)?;
+        Ok(alpha_s.powi(self.alpha_s_power as i32))
+    }
+
+    // the parton luminosity of a channel
+    fn lumi(
+        &self,
+        pdf: &(impl PdfProvider + ?Sized),
+        channel: &Channel,
+        x1: f64,
+        x2: f64,
+        mu_f: f64,
+    ) -> f64 {
+        let [beam1, beam2] = self.init.incoming.beams;
+        let q2 = mu_f * mu_f;
+        channel
+            .pairs()
+            .map(|(a, b)| {
+                let f1 = pdf.xfx_q2(in_beam(beam1, a), x1, q2) / x1;
+                let f2 = pdf.xfx_q2(in_beam(beam2, b), x2, q2) / x2;
+                f1 * f2
+            })
+            .sum()
+    }
+}
+
+impl fmt::Debug for PdfReweighter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PdfReweighter")
+            .field("init", &self.init)
+            .field("alpha_s_power", &self.alpha_s_power)
+            .field("logs", &self.logs)
+            .field("fallback_alpha_s", &self.fallback_alpha_s)
+            .finish_non_exhaustive()
+    }
+}
+
+// the parton in the proton corresponding to `parton` in `beam`
+fn in_beam(beam: ParticleID, parton: ParticleID) -> ParticleID {
+    if beam.is_anti_particle() && (parton.is_quark() || parton.is_anti_quark())
+    {
+        parton.anti()
+    } else {
+        parton
+    }
+}
+
+/// Toy PDFs with analytic parton densities
+///
+/// The light-quark and gluon densities only reproduce the starting
+/// scale `Q^2 = 2 GeV^2` of the Les Houches toy PDFs: they do not
+/// depend on the scale and there are no charm or bottom densities.
+/// The strong coupling runs at one loop with five flavours from the
+/// given value at the Z mass.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct ToyPdf {
+    pub alpha_s_mz: Option<f64>,
+}
+
+impl Default for ToyPdf {
+    fn default() -> Self {
+        Self {
+            alpha_s_mz: Some(0.118),
+        }
+    }
+}
+
+const MZ: f64 = 91.1876;
+
+impl PdfProvider for ToyPdf {
+    fn xfx_q2(&self, id: ParticleID, x: f64, _q2: f64) -> f64 {
+        let xdbar = 0.1939875 * x.powf(-0.1) * (1. - x).powi(6);
+        let xubar = (1. - x) * xdbar;
+        let xs = 0.2 * (xubar + xdbar);
+        let xuv = 5.1072 * x.powf(0.8) * (1. - x).powi(3);
+        let xdv = 3.06432 * x.powf(0.8) * (1. - x).powi(4);
+        match id.id() {
+            21 => 1.7 * x.powf(-0.1) * (1. - x).powi(5),
+            1 => xdv + xdbar,
+            2 => xuv + xubar,
+            -1 => xdbar,
+            -2 => xubar,
+            3 | -3 => xs,
+            _ => 0.,
+        }
+    }
+
+    fn alpha_s_q2(&self, q2: f64) -> Option<f64> {
+        let alpha_s_mz = self.alpha_s_mz?;
+        let b0 = (33. - 2. * 5.) / (12. * std::f64::consts::PI);
+        Some(alpha_s_mz / (1. + b0 * alpha_s_mz * (q2 / (MZ * MZ)).ln()))
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ReweightErr {
+    #[error("Channel {0} is not defined")]
+    UnknownChannel(u32),
+    #[error("Expected {expected} log coefficients, found {actual}")]
+    NumLogCoeff { expected: usize, actual: usize },
+    #[error("'{0}' is not a known logarithm")]
+    InvalidLogTerm(String),
+    #[error("The PDF provider has no strong coupling")]
+    NoAlphaS,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{rel_diff, INIT};
+
+    const SUBEVENT: &str = r#"<se w="0.0005614095335" muR="517.0997809" muF="517.0997809">
+<p id="1,1"> 327.0442813,0,0,327.0442813 </p>
+<p id="1,-1"> 4344.52245,0,0,-4344.52245 </p>
+<p id="0,6"> 3334.580936,-386.757619,943.5205498,-3170.151619 </p>
+<p id="0,-6"> 1336.985795,386.757619,-943.5205498,-847.3265495 </p>
+<rw ch="1"> 0.05031450481,0.6683880692,-0.0004385904665 </rw>
+<rw ch="0"> 0.05031450481,0.6683880692,0.001 </rw>
+</se>"#;
+
+    // unit parton densities and a fixed strong coupling
+    struct Flat(Option<f64>);
+
+    impl PdfProvider for Flat {
+        fn xfx_q2(&self, _id: ParticleID, x: f64, _q2: f64) -> f64 {
+            x
+        }
+
+        fn alpha_s_q2(&self, _q2: f64) -> Option<f64> {
+            self.0
+        }
+    }
+
+    #[test]
+    fn closure() {
+        let init: Init = quick_xml::de::from_str(INIT).unwrap();
+        let pdf = ToyPdf::default();
+        let reweighter = PdfReweighter::new(&init, 2, &pdf);
+        let subevent: SubEvent = quick_xml::de::from_str(SUBEVENT).unwrap();
+        let weight = reweighter.subevent_weight(&subevent, &pdf).unwrap();
+        assert!(rel_diff(weight, subevent.weight) < 1e-12);
+
+        // as in the original record, without the gluon channel
+        let subevent: SubEvent = quick_xml::de::from_str(
+            &SUBEVENT
+                .replace("0.0005614095335", "-0.0004385904665")
+                .replace(
+                    "<rw ch=\"0\"> 0.05031450481,0.6683880692,0.001 </rw>\n",
+                    "",
+                ),
+        )
+        .unwrap();
+        assert_eq!(subevent.reweight.len(), 1);
+        let weight = reweighter.subevent_weight(&subevent, &pdf).unwrap();
+        assert!(rel_diff(weight, subevent.weight) < 1e-12);
+    }
+
+    #[test]
+    fn reweight_toy() {
+        let init: Init = quick_xml::de::from_str(INIT).unwrap();
+        let subevent: SubEvent = quick_xml::de::from_str(SUBEVENT).unwrap();
+        let pdf = ToyPdf::default();
+        let original = Flat(Some(0.1));
+        let reweighter = PdfReweighter::new(&init, 2, &original);
+
+        let (x1, x2) = (0.05031450481, 0.6683880692);
+        let mu2 = 517.0997809f64.powi(2);
+        let f = |id, x| pdf.xfx_q2(ParticleID::new(id), x, mu2) / x;
+        let ratio = (pdf.alpha_s_q2(mu2).unwrap() / 0.1).powi(2);
+        let qqbar = f(1, x1) * f(-1, x2) + f(2, x1) * f(-2, x2);
+        let gg = f(21, x1) * f(21, x2);
+        let expected = ratio * (-0.0004385904665 * qqbar / 2. + 0.001 * gg);
+        let weight = reweighter.subevent_weight(&subevent, &pdf).unwrap();
+        assert!(rel_diff(weight, expected) < 1e-12);
+        let mut event = Event {
+            subevents: vec![subevent.clone(), subevent],
+            ..Default::default()
+        };
+        let total = reweighter.event_weight(&event, &pdf).unwrap();
+        assert!(rel_diff(total, 2. * expected) < 1e-12);
+        reweighter.reweight(&mut event, &pdf).unwrap();
+        assert_eq!(event.subevents[1].weight, weight);
+
+        // antiproton in the second beam
+        let mut init_pbar = init.clone();
+        init_pbar.incoming.beams[1] = init.incoming.beams[1].anti();
+        let reweighter_pbar = PdfReweighter::new(&init_pbar, 2, &original);
+        let weight_pbar = reweighter_pbar
+            .subevent_weight(&event.subevents[0], &pdf)
+            .unwrap();
+        let qqbar = f(1, x1) * f(1, x2) + f(2, x1) * f(2, x2);
+        let expected = ratio * (-0.0004385904665 * qqbar / 2. + 0.001 * gg);
+        assert!(rel_diff(weight_pbar, expected) < 1e-12);
+    }
+
+    #[test]
+    fn reweight_logs() {
+        let init: Init = quick_xml::de::from_str(INIT).unwrap();
+        let rw = normalization::Reweight {
+            rwentry: ["x1", "x2", "log(muR**2)", "log(muF**2)**2"]
+                .map(String::from)
+                .to_vec(),
+        };
+        let logs = log_terms(&rw).unwrap();
+        assert_eq!(logs[1].to_string(), "log(muF**2)**2");
+        let original = Flat(Some(1.));
+        let reweighter =
+            PdfReweighter::new(&init, 0, &original).logs(&rw).unwrap();
+        let subevent = SubEvent {
+            mu_r: 10.,
+            mu_f: 20.,
+            reweight: vec![crate::Reweight {
+                channel: 0,
+                reweights: crate::Reweights {
+                    x1: 0.1,
+                    x2: 0.2,
+                    log_coeff: vec![1., 2., 3.],
+                },
+            }],
+            ..Default::default()
+        };
+        let pdf = ToyPdf::default();
+        let f = |x| pdf.xfx_q2(ParticleID::new(21), x, 400.) / x;
+        let poly = 1. + 2. * 100f64.ln() + 3. * 400f64.ln().powi(2);
+        let expected = f(0.1) * f(0.2) * poly;
+        let weight = reweighter.subevent_weight(&subevent, &pdf).unwrap();
+        assert!(rel_diff(weight, expected) < 1e-12);
+
+        let reweighter = PdfReweighter::new(&init, 0, &original);
+        assert!(matches!(
+            reweighter.subevent_weight(&subevent, &pdf),
+            Err(ReweightErr::NumLogCoeff {
+                expected: 1,
+                actual: 3
+            })
+        ));
+        let no_alpha_s = ToyPdf { alpha_s_mz: None };
+        let reweighter =
+            PdfReweighter::new(&init, 2, &original).logs(&rw).unwrap();
+        assert!(matches!(
+            reweighter.subevent_weight(&subevent, &no_alpha_s),
+            Err(ReweightErr::NoAlphaS)
+        ));
+        let alpha_s = AlphaS::new(0.118);
+        let reweighter = reweighter.fallback_alpha_s(alpha_s);
+        let weight =
+            reweighter.subevent_weight(&subevent, &no_alpha_s).unwrap();
+        let expected = expected * alpha_s.alpha_s(10.).powi(2);
+        assert!(rel_diff(weight, expected) < 1e-12);
+        assert!(matches!(
+            "log(muQ**2)".parse::<LogTerm>(),
+            Err(ReweightErr::InvalidLogTerm(_))
+        ));
+    }
+}
@@ -0,0 +1,277 @@
+//! Renormalisation and factorisation scale variations
+//!
+//! The explicit scale dependence of a subevent weight is encoded in the
+//! coefficients of the logarithms of the reweighting entries, see
+//! [reweight](crate::reweight). Multiplying the scales by factors
+//! `(xi_R, xi_F)` changes the logarithms, the strong coupling, and the
+//! parton densities, and [PdfReweighter] evaluates the weights at the
+//! varied scales.
+//!
+//! The usual estimate of the scale uncertainty is the [Envelope] of the
+//! results obtained with the [SEVEN_POINT] or [NINE_POINT] variations.
+use crate::reweight::{PdfProvider, PdfReweighter, ReweightErr};
+use crate::{Event, SubEvent};
+
+/// Factors multiplying the renormalisation and factorisation scales
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct ScaleFactors {
+    pub xi_r: f64,
+    pub xi_f: f64,
+}
+
+impl ScaleFactors {
+    /// The central scales
+    pub const CENTRAL: Self = Self::new(1., 1.);
+
+    pub const fn new(xi_r: f64, xi_f: f64) -> Self {
+        Self { xi_r, xi_f }
+    }
+}
+
+impl Default for ScaleFactors {
+    fn default() -> Self {
+        Self::CENTRAL
+    }
+}
+
+/// Variations by factors of two, omitting opposite variations of the
+/// two scales
+///
+/// The central scales come first.
+pub const SEVEN_POINT: [ScaleFactors; 7] = [
+    ScaleFactors::CENTRAL,
+    ScaleFactors::new(2., 2.),
+    ScaleFactors::new(0.5, 0.5),
+    ScaleFactors::new(2., 1.),
+    ScaleFactors::new(1., 2.),
+    ScaleFactors::new(0.5, 1.),
+    ScaleFactors::new(1., 0.5),
+];
+
+/// All independent variations of the two scales by factors of two
+///
+/// The central scales come first.
+pub const NINE_POINT: [ScaleFactors; 9] = [
+    ScaleFactors::CENTRAL,
+    ScaleFactors::new(2., 2.),
+    ScaleFactors::new(0.5, 0.5),
+    ScaleFactors::new(2., 1.),
+    ScaleFactors::new(1., 2.),
+    ScaleFactors::new(0.5, 1.),
+    ScaleFactors::new(1., 0.5),
+    ScaleFactors::new(2., 0.5),
+    ScaleFactors::new(0.5, 2.),
+];
+
+impl PdfReweighter<'_> {
+    /// The weight of a subevent with varied scales
+    pub fn scale_varied_weight(
+        &self,
+        subevent: &SubEvent,
+        pdf: &impl PdfProvider,
+        factors: ScaleFactors,
+    ) -> Result<f64, ReweightErr> {
+        self.weight_at(
+            subevent,
+            pdf,
+            factors.xi_r * subevent.mu_r,
+            factors.xi_f * subevent.mu_f,
+        )
+    }
+
+    /// The weights of a subevent for each of the given scale variations
+    pub fn scale_varied_weights(
+        &self,
+        subevent: &SubEvent,
+        pdf: &impl PdfProvider,
+        factors: &[ScaleFactors],
+    ) -> Result<Vec<f64>, ReweightErr> {
+        factors
+            .iter()
+            .map(|f| self.scale_varied_weight(subevent, pdf, *f))
+            .collect()
+    }
+
+    /// The weights of an event for each of the given scale variations
+    ///
+    /// Each weight is the sum of the varied subevent weights.
+    pub fn event_scale_varied_weights(
+        &self,
+        event: &Event,
+        pdf: &impl PdfProvider,
+        factors: &[ScaleFactors],
+    ) -> Result<Vec<f64>, ReweightErr> {
+        let mut weights = vec![0.; factors.len()];
+        for subevent in &event.subevents {
+            let varied = self.scale_varied_weights(subevent, pdf, factors)?;
+            for (weight, varied) in weights.iter_mut().zip(varied) {
+                *weight += varied;
+            }
+        }
+        Ok(weights)
+    }
+}
+
+/// Central value with the minimum and maximum over variations
+#[derive(Copy, Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct Envelope {
+    pub central: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Envelope {
+    /// The envelope of the given values
+    ///
+    /// The first value is the central one, as in [SEVEN_POINT] and
+    /// [NINE_POINT]. Returns `None` if there are no values.
+    pub fn new(values: &[f64]) -> Option<Self> {
+        let central = *values.first()?;
+        let min = values.iter().copied().fold(central, f64::min);
+        let max = values.iter().copied().fold(central, f64::max);
+        Some(Self { central, min, max })
+    }
+
+    /// The envelopes of histograms filled for each variation
+    ///
+    /// `histograms` contains the bin contents obtained for each
+    /// variation, with the central one first. The result contains the
+    /// envelope for each bin. Returns `None` if there are no histograms
+    /// or if they have different numbers of bins.
+    pub fn bins<H: AsRef<[f64]>>(histograms: &[H]) -> Option<Vec<Self>> {
+        let nbins = histograms.first()?.as_ref().len();
+        if histograms.iter().any(|h| h.as_ref().len() != nbins) {
+            return None;
+        }
+        let envelopes = (0..nbins)
+            .map(|bin| {
+                let values: Vec<_> =
+                    histograms.iter().map(|h| h.as_ref()[bin]).collect();
+                Self::new(&values).unwrap()
+            })
+            .collect();
+        Some(envelopes)
+    }
+
+    /// The downward deviation from the central value
+    pub fn lower(&self) -> f64 {
+        self.central - self.min
+    }
+
+    /// The upward deviation from the central value
+    pub fn upper(&self) -> f64 {
+        self.max - self.central
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use particle_id::ParticleID;
+
+    use super::*;
+    use crate::channels::Init;
+    use crate::normalization;
+    use crate::reweight::ToyPdf;
+    use crate::test_util::{rel_diff, INIT, RECORD};
+    use crate::Eventrecord;
+
+    #[test]
+    fn vary_scales() {
+        let init: Init = quick_xml::de::from_str(INIT).unwrap();
+        let rw = normalization::Reweight {
+            rwentry: ["x1", "x2", "log(muR**2)", "log(muF**2)"]
+                .map(String::from)
+                .to_vec(),
+        };
+        let pdf = ToyPdf::default();
+        let reweighter = PdfReweighter::new(&init, 2, &pdf).logs(&rw).unwrap();
+        let subevent = SubEvent {
+            mu_r: 10.,
+            mu_f: 20.,
+            reweight: vec![crate::Reweight {
+                channel: 0,
+                reweights: crate::Reweights {
+                    x1: 0.1,
+                    x2: 0.2,
+                    log_coeff: vec![1., 2., 3.],
+                },
+            }],
+            ..Default::default()
+        };
+        let pdf_factor = |mu_r: f64, mu_f: f64| {
+            let f = |x| pdf.xfx_q2(ParticleID::new(21), x, mu_f * mu_f) / x;
+            let alpha_s = pdf.alpha_s_q2(mu_r * mu_r).unwrap();
+            f(0.1) * f(0.2) * alpha_s.powi(2)
+        };
+        let expected = |mu_r: f64, mu_f: f64| {
+            let poly = 1. + 2. * (mu_r * mu_r).ln() + 3. * (mu_f * mu_f).ln();
+            poly * pdf_factor(mu_r, mu_f) / pdf_factor(10., 20.)
+        };
+
+        let central = reweighter.subevent_weight(&subevent, &pdf).unwrap();
+        let weights = reweighter
+            .scale_varied_weights(&subevent, &pdf, &SEVEN_POINT)
+            .unwrap();
+        assert_eq!(weights.len(), 7);
+        assert_eq!(weights[0], central);
+        for (weight, xi) in weights.iter().zip(SEVEN_POINT) {
+            let expected = expected(10. * xi.xi_r, 20. * xi.xi_f);
+            assert!(rel_diff(*weight, expected) < 1e-12);
+        }
+
+        let event = Event {
+            subevents: vec![subevent.clone(), subevent],
+            ..Default::default()
+        };
+        let event_weights = reweighter
+            .event_scale_varied_weights(&event, &pdf, &NINE_POINT)
+            .unwrap();
+        assert_eq!(event_weights.len(), 9);
+        for (weight, xi) in event_weights.iter().zip(NINE_POINT) {
+            let expected = 2. * expected(10. * xi.xi_r, 20. * xi.xi_f);
+            assert!(rel_diff(*weight, expected) < 1e-12);
+        }
+    }
+
+    #[test]
+    fn central_weight() {
+        let init: Init = quick_xml::de::from_str(INIT).unwrap();
+        let record: Eventrecord = quick_xml::de::from_str(RECORD).unwrap();
+        let subevent = &record.events[1].subevents[0];
+        let pdf = ToyPdf::default();
+        let reweighter = PdfReweighter::new(&init, 2, &pdf);
+        let weights = reweighter
+            .scale_varied_weights(subevent, &pdf, &SEVEN_POINT)
+            .unwrap();
+        assert!(rel_diff(weights[0], subevent.weight) < 1e-12);
+        // the toy PDFs do not depend on the factorisation scale
+        for (weight, xi) in weights.iter().zip(SEVEN_POINT) {
+            assert_eq!(*weight == weights[0], xi.xi_r == 1.);
+        }
+    }
+
+    #[test]
+    fn envelope() {
+        assert_eq!(Envelope::new(&[]), None);
+        let env = Envelope::new(&[1., 1.5, 0.7, 1.2]).unwrap();
+        assert_eq!(
+            env,
+            Envelope {
+                central: 1.,
+                min: 0.7,
+                max: 1.5
+            }
+        );
+        assert!((env.lower() - 0.3).abs() < 1e-15);
+        assert_eq!(env.upper(), 0.5);
+
+        let histograms = [vec![1., 2.], vec![0.5, 3.], vec![1.5, 2.5]];
+        let envelopes = Envelope::bins(&histograms).unwrap();
+        assert_eq!(envelopes.len(), 2);
+        assert_eq!(envelopes[0], Envelope::new(&[1., 0.5, 1.5]).unwrap());
+        assert_eq!(envelopes[1], Envelope::new(&[2., 3., 2.5]).unwrap());
+        let mismatched = [vec![1., 2.], vec![0.5]];
+        assert_eq!(Envelope::bins(&mismatched), None);
+        assert_eq!(Envelope::bins::<Vec<f64>>(&[]), None);
+    }
+}
@@ -0,0 +1,289 @@
+//! Renormalisation and factorisation scale choices
+//!
+//! The scales used for a record are described by [Init::scales] in
+//! the form `muR = <scale>, muF = <scale>`. Each scale is a product
+//! or quotient of numeric factors and at most one dynamical scale:
+//!
+//! - `HT`: a quarter of the scalar sum of the transverse masses of
+//!   all outgoing particles,
+//! - `mT(<PDG id>)`: the transverse mass of the first outgoing
+//!   particle with the given id.
+//!
+//! Examples are `HT/2`, `0.5*mT(6)`, or the fixed scale `173.3`.
+//!
+//! The normalisation of `HT` follows STRIPPER, which labels the usual
+//! top-quark pair scale `H_T/4` as `HT`: records declaring
+//! `muR = HT, muF = HT` store `mu_r = mu_f = (mT(6) + mT(-6)) / 4`.
+//!
+//! [Init::scales]: crate::channels::Init::scales
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use particle_id::ParticleID;
+
+use crate::{Momentum, ParseErr, Particle, Status, SubEvent};
+
+/// Renormalisation and factorisation scale
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+pub struct ScaleChoice {
+    pub mu_r: Scale,
+    pub mu_f: Scale,
+}
+
+impl ScaleChoice {
+    /// The renormalisation scale for the given subevent
+    pub fn mu_r(&self, subevent: &SubEvent) -> Option<f64> {
+        self.mu_r.eval(&subevent.particles)
+    }
+
+    /// The factorisation scale for the given subevent
+    pub fn mu_f(&self, subevent: &SubEvent) -> Option<f64> {
+        self.mu_f.eval(&subevent.particles)
+    }
+}
+
+impl FromStr for ScaleChoice {
+    type Err = ParseErr;
+
+    fn from_str(scales_str: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseErr::InvalidScale(scales_str.to_owned());
+        let mut mu_r = None;
+        let mut mu_f = None;
+        for def in scales_str.split(',') {
+            let Some((name, scale)) = def.split_once('=') else {
+                return Err(invalid());
+            };
+            let scale = Some(scale.parse()?);
+            match name.trim() {
+                "muR" => mu_r = scale,
+                "muF" => mu_f = scale,
+                "mu" => {
+                    mu_r = scale.clone();
+                    mu_f = scale;
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        let (Some(mu_r), Some(mu_f)) = (mu_r, mu_f) else {
+            return Err(invalid());
+        };
+        Ok(Self { mu_r, mu_f })
+    }
+}
+
+impl Display for ScaleChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "muR = {}, muF = {}", self.mu_r, self.mu_f)
+    }
+}
+
+/// A single scale
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+pub enum Scale {
+    /// Fixed value
+    Fixed(f64),
+    /// Multiple of a dynamical scale
+    Dynamic { factor: f64, scale: DynamicScale },
+}
+
+impl Scale {
+    /// Evaluate the scale for the given particles
+    ///
+    /// Returns `None` if the scale depends on a particle that is not
+    /// present.
+    pub fn eval(&self, particles: &[Particle]) -> Option<f64> {
+        match self {
+            Self::Fixed(mu) => Some(*mu),
+            Self::Dynamic { factor, scale } => {
+                scale.eval(particles).map(|mu| factor * mu)
+            }
+        }
+    }
+}
+
+impl FromStr for Scale {
+    type Err = ParseErr;
+
+    fn from_str(scale_str: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseErr::InvalidScale(scale_str.to_owned());
+        let mut factor = 1.;
+        let mut dynamic = None;
+        let mut rest = scale_str.trim();
+        let mut divide = false;
+        loop {
+            let end = rest.find(['*', '/']).unwrap_or(rest.len());
+            let term = rest[..end].trim();
+            if let Ok(x) = term.parse::<f64>() {
+                if !x.is_finite() || (divide && x == 0.) {
+                    return Err(invalid());
+                }
+                factor = if divide { factor / x } else { factor * x };
+            } else {
+                if divide || dynamic.is_some() {
+                    return Err(invalid());
+                }
+                dynamic = Some(term.parse().map_err(|_| invalid())?);
+            }
+            let Some(op) = rest[end..].chars().next() else {
+                break;
+            };
+            divide = op == '/';
+            rest = &rest[end + 1..];
+        }
+        Ok(match dynamic {
+            Some(scale) => Self::Dynamic { factor, scale },
+            None => Self::Fixed(factor),
+        })
+    }
+}
+
+impl Display for Scale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fixed(mu) => write!(f, "{mu}"),
+            Self::Dynamic { factor, scale } => {
+                let inverse = 1. / factor;
+                if *factor == 1. {
+                    write!(f, "{scale}")
+                } else if inverse.fract() == 0. && inverse > 0. {
+                    write!(f, "{scale}/{inverse}")
+                } else {
+                    write!(f, "{factor}*{scale}")
+                }
+            }
+        }
+    }
+}
+
+/// A scale depending on the kinematics of a subevent
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum DynamicScale {
+    /// A quarter of the scalar sum of the transverse masses of the
+    /// outgoing particles
+    HT,
+    /// Transverse mass of an outgoing particle
+    MT(ParticleID),
+}
+
+impl DynamicScale {
+    /// Evaluate the scale for the given particles
+    pub fn eval(&self, particles: &[Particle]) -> Option<f64> {
+        let mut outgoing =
+            particles.iter().filter(|p| p.id.status == Status::Outgoing);
+        match self {
+            Self::HT => {
+                Some(outgoing.map(|p| mt(&p.momentum)).sum::<f64>() / 4.)
+            }
+            Self::MT(id) => outgoing
+                .find(|p| p.id.pdg_id == *id)
+                .map(|p| mt(&p.momentum)),
+        }
+    }
+}
+
+impl FromStr for DynamicScale {
+    type Err = ParseErr;
+
+    fn from_str(scale_str: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseErr::InvalidScale(scale_str.to_owned());
+        if scale_str == "HT" {
+            return Ok(Self::HT);
+        }
+        let id = scale_str
+            .strip_prefix("mT(")
+            .and_then(|s| s.strip_suffix(')'))
+            .ok_or_else(invalid)?;
+        let id = id.trim().parse().map_err(|_| invalid())?;
+        Ok(Self::MT(ParticleID::new(id)))
+    }
+}
+
+impl Display for DynamicScale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HT => f.write_str("HT"),
+            Self::MT(id) => write!(f, "mT({})", id.id()),
+        }
+    }
+}
+
+// transverse mass, sqrt(m^2 + pT^2) = sqrt(E^2 - pz^2)
+fn mt(p: &Momentum) -> f64 {
+    let [e, _, _, pz] = p.0;
+    (e * e - pz * pz).max(0.).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::channels::Init;
+    use crate::test_util::{INIT, SUBEVENT};
+
+    #[test]
+    fn parse_scales() {
+        let scales: ScaleChoice = " muR = HT, muF = HT ".parse().unwrap();
+        assert_eq!(scales.mu_r, scales.mu_f);
+        assert_eq!(scales.to_string(), "muR = HT, muF = HT");
+
+        let scales: ScaleChoice =
+            "muR = HT/2, muF = 2*mT(6)/4".parse().unwrap();
+        assert_eq!(
+            scales.mu_r,
+            Scale::Dynamic {
+                factor: 0.5,
+                scale: DynamicScale::HT
+            }
+        );
+        assert_eq!(
+            scales.mu_f,
+            Scale::Dynamic {
+                factor: 0.5,
+                scale: DynamicScale::MT(ParticleID::new(6))
+            }
+        );
+        assert_eq!(scales.to_string(), "muR = HT/2, muF = mT(6)/2");
+
+        let scales: ScaleChoice = "mu = 173.3".parse().unwrap();
+        assert_eq!(scales.mu_r, Scale::Fixed(173.3));
+        assert_eq!(scales.mu_f, Scale::Fixed(173.3));
+        let scales: ScaleChoice = "muR = 0.3*HT, muF = HT".parse().unwrap();
+        assert_eq!(scales.to_string(), "muR = 0.3*HT, muF = HT");
+
+        for invalid in [
+            "muR = HT",
+            "muR = HT, muF = pT",
+            "muR = HT*HT, muF = HT",
+            "muR = 2/HT, muF = HT",
+            "muR = HT/0, muF = HT",
+            "muX = HT, muF = HT",
+            "muR HT, muF = HT",
+        ] {
+            assert!(matches!(
+                invalid.parse::<ScaleChoice>(),
+                Err(ParseErr::InvalidScale(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn eval_scales() {
+        let subevent: SubEvent = quick_xml::de::from_str(SUBEVENT).unwrap();
+        let init: Init = quick_xml::de::from_str(INIT).unwrap();
+        let scales = init.scale_choice().unwrap();
+        let mu_r = scales.mu_r(&subevent).unwrap();
+        assert!((mu_r - subevent.mu_r).abs() < 1e-6 * subevent.mu_r);
+        let mu_f = scales.mu_f(&subevent).unwrap();
+        assert!((mu_f - subevent.mu_f).abs() < 1e-6 * subevent.mu_f);
+
+        let top: Scale = "mT(6)".parse().unwrap();
+        let anti_top: Scale = "mT(-6)".parse().unwrap();
+        let ht: Scale = "HT".parse().unwrap();
+        let sum = top.eval(&subevent.particles).unwrap()
+            + anti_top.eval(&subevent.particles).unwrap();
+        let ht = ht.eval(&subevent.particles).unwrap();
+        assert!((sum / 4. - ht).abs() < 1e-9);
+        let bottom: Scale = "mT(5)".parse().unwrap();
+        assert_eq!(bottom.eval(&subevent.particles), None);
+    }
+}
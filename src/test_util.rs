@@ -0,0 +1,86 @@
+/// Initial states, scales, and channels of the test events
+pub(crate) const INIT: &str = r#"<Init>
+  <Incoming> p p with NNPDF31_nnlo_as_0118/0 </Incoming>
+  <Scales> muR = HT, muF = HT </Scales>
+  <Channels>
+    <Channel> 0,1,21,21 </Channel>
+    <Channel> 1,2,1,-1,2,-2 </Channel>
+  </Channels>
+</Init>"#;
+
+/// Normalisation of the test record
+pub(crate) const NORMALIZATION: &str = r#"<Normalization name="Bm">
+<XSection>
+ <XSNeg> 687.103,0.978277 </XSNeg>
+ <MaxWeightNeg> 796475 </MaxWeightNeg>
+ <TotalEventsNeg> 488338734 </TotalEventsNeg>
+ <AcceptedEventsNeg> 493310 </AcceptedEventsNeg>
+ <FactorNeg> 803.98,1.14468 </FactorNeg>
+</XSection>
+<Contribution name="Bm">
+  <xsection> 687.103,0.978277</xsection>
+  <rw>
+    <rwentry> x1 </rwentry>
+    <rwentry> x2 </rwentry>
+  </rw>
+</Contribution>
+<NumberOfRejectedEvents> 0 , 100</NumberOfRejectedEvents>
+</Normalization>"#;
+
+/// A subevent as written by STRIPPER
+pub(crate) const SUBEVENT: &str = r#"<se w="-0.0002369763508" muR="91.16253934" muF="91.16253934">
+<p id="1,21"> 5780.608219,0,0,5780.608219 </p>
+<p id="1,21"> 334.3891359,0,0,-334.3891359 </p>
+<p id="0,6"> 357.9061187,-58.25473457,9.621341818,-307.9843429 </p>
+<p id="0,-6"> 5757.091237,58.25473457,-9.621341818,5754.203426 </p>
+<rw ch="12"> 0.8893243414,0.05144448245,-0.0002369763508 </rw>
+</se>"#;
+
+/// A record as written by STRIPPER
+pub(crate) const RECORD: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Eventrecord nevents="3" nsubevents="4" nreweights="4" as="2" name="Bm">
+<!--
+File generated with STRIPPER v0.1 for online data base
+-->
+<e>
+<se w="-0.0002369763508" muR="91.16253934" muF="91.16253934">
+<p id="1,21"> 5780.608219,0,0,5780.608219 </p>
+<p id="1,21"> 334.3891359,0,0,-334.3891359 </p>
+<p id="0,6"> 357.9061187,-58.25473457,9.621341818,-307.9843429 </p>
+<p id="0,-6"> 5757.091237,58.25473457,-9.621341818,5754.203426 </p>
+<rw ch="12"> 0.8893243414,0.05144448245,-0.0002369763508 </rw>
+</se>
+</e>
+<!-- <e> in a comment is not an event -->
+<e>
+<se w="-0.0004385904665" muR="517.0997809" muF="517.0997809">
+<p id="1,1"> 327.0442813,0,0,327.0442813 </p>
+<p id="1,-1"> 4344.52245,0,0,-4344.52245 </p>
+<p id="0,6"> 3334.580936,-386.757619,943.5205498,-3170.151619 </p>
+<p id="0,-6"> 1336.985795,386.757619,-943.5205498,-847.3265495 </p>
+<rw ch="1"> 0.05031450481,0.6683880692,-0.0004385904665 </rw>
+</se>
+<se w="-2.098171554e-05" muR="1140.717994" muF="1140.717994">
+<p id="1,1"> 1610.067985,0,0,1610.067985 </p>
+<p id="1,-1"> 4034.720799,0,0,-4034.720799 </p>
+<p id="0,6"> 3362.889308,-2194.656814,598.8951393,-2470.642493 </p>
+<p id="0,-6"> 2281.899476,2194.656814,-598.8951393,45.98967904 </p>
+<rw ch="1"> 0.2477027669,0.6207262768,-2.098171554e-05 </rw>
+</se>
+</e>
+<e>
+<se w="-4.834595231e-05" muR="635.8532498" muF="635.8532498">
+<p id="1,1"> 1299.986308,0,0,1299.986308 </p>
+<p id="1,-1"> 3291.996472,0,0,-3291.996472 </p>
+<p id="0,6"> 1510.408466,-1129.902831,557.4950784,814.9210479 </p>
+<p id="0,-6"> 3081.574313,1129.902831,-557.4950784,-2806.931212 </p>
+<rw ch="1"> 0.1999978935,0.5064609956,-4.834595231e-05 </rw>
+</se>
+</e>
+</Eventrecord>
+"#;
+
+/// Relative difference between two numbers
+pub(crate) fn rel_diff(a: f64, b: f64) -> f64 {
+    (a - b).abs() / (a.abs() + b.abs())
+}
@@ -0,0 +1,333 @@
+//! Consistency checks for event records
+//!
+//! A [Validator] compares the counts declared in the header of a
+//! record with its content. Optionally, it also checks the
+//! reweighting entries against the channels listed in an [Init] and
+//! the entries listed in a [Normalization].
+//!
+//! Events can be passed to the validator one at a time, so that
+//! records can be checked while streaming them with an
+//! [EventrecordReader].
+use std::collections::HashSet;
+use std::io::BufRead;
+
+use crate::channels::Init;
+use crate::normalization::Normalization;
+use crate::{
+    Event, Eventrecord, EventrecordHeader, EventrecordReader, ReadErr,
+};
+
+/// Incremental consistency check of a record
+#[derive(Clone, Debug)]
+pub struct Validator {
+    header: EventrecordHeader,
+    channels: Option<HashSet<u32>>,
+    nlog_coeff: Option<usize>,
+    nevents: u64,
+    // unknown when resuming after skipped events
+    nsubevents: Option<u64>,
+    nreweights: Option<u64>,
+    issues: Vec<Issue>,
+}
+
+impl Validator {
+    /// Start validating a record with the given header
+    pub fn new(header: EventrecordHeader) -> Self {
+        Self {
+            header,
+            channels: None,
+            nlog_coeff: None,
+            nevents: 0,
+            nsubevents: Some(0),
+            nreweights: Some(0),
+            issues: Vec::new(),
+        }
+    }
+
+    // continue after events that were not passed to the validator
+    fn resume(
+        mut self,
+        (nevents, nsubevents, nreweights): (u64, Option<u64>, Option<u64>),
+    ) -> Self {
+        self.nevents = nevents;
+        self.nsubevents = nsubevents;
+        self.nreweights = nreweights;
+        self
+    }
+
+    /// Check that all reweighting channels are listed in `init`
+    pub fn init(mut self, init: &Init) -> Self {
+        let channels = init.channels.iter().map(|ch| ch.index());
+        self.channels = Some(channels.collect());
+        self
+    }
+
+    /// Check the number of log coefficients against `normalization`
+    pub fn normalization(mut self, normalization: &Normalization) -> Self {
+        self.nlog_coeff = Some(normalization.contribution.rw.nlog_coeff());
+        self
+    }
+
+    /// Check the next event
+    pub fn event(&mut self, event: &Event) {
+        let nevent = self.nevents;
+        self.nevents += 1;
+        if let Some(n) = &mut self.nsubevents {
+            *n += event.subevents.len() as u64;
+        }
+        for (nsubevent, subevent) in event.subevents.iter().enumerate() {
+            if let Some(n) = &mut self.nreweights {
+                *n += subevent.reweight.len() as u64;
+            }
+            for rw in &subevent.reweight {
+                if let Some(channels) = &self.channels {
+                    if !channels.contains(&rw.channel) {
+                        self.issues.push(Issue::UnknownChannel {
+                            event: nevent,
+                            subevent: nsubevent,
+                            channel: rw.channel,
+                        });
+                    }
+                }
+                let actual = rw.reweights.log_coeff.len();
+                match self.nlog_coeff {
+                    Some(expected) if expected != actual => {
+                        self.issues.push(Issue::NumLogCoeff {
+                            event: nevent,
+                            subevent: nsubevent,
+                            expected,
+                            actual,
+                        })
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Finish the validation after the last event
+    pub fn finish(mut self) -> ValidationReport {
+        let counts = [
+            ("nevents", self.header.nevents, Some(self.nevents)),
+            ("nsubevents", self.header.nsubevents, self.nsubevents),
+            ("nreweights", self.header.nreweights, self.nreweights),
+        ];
+        for (attribute, declared, actual) in counts {
+            let Some(actual) = actual else {
+                continue;
+            };
+            if declared != actual {
+                self.issues.push(Issue::CountMismatch {
+                    attribute,
+                    declared,
+                    actual,
+                });
+            }
+        }
+        ValidationReport {
+            issues: self.issues,
+        }
+    }
+}
+
+/// The result of validating a record
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct ValidationReport {
+    issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    /// Whether the record passed all checks
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// The problems found in the record
+    pub fn issues(&self) -> &[Issue] {
+        &self.issues
+    }
+}
+
+/// A problem found during validation
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Issue {
+    /// A count in the header does not match the content
+    CountMismatch {
+        attribute: &'static str,
+        declared: u64,
+        actual: u64,
+    },
+    /// A reweighting entry refers to a channel that is not defined
+    UnknownChannel {
+        event: u64,
+        subevent: usize,
+        channel: u32,
+    },
+    /// A reweighting entry has the wrong number of log coefficients
+    NumLogCoeff {
+        event: u64,
+        subevent: usize,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl Eventrecord {
+    /// Check the record for consistency
+    ///
+    /// See [Validator] for details.
+    pub fn validate(
+        &self,
+        init: Option<&Init>,
+        normalization: Option<&Normalization>,
+    ) -> ValidationReport {
+        let mut validator = Validator::new(self.header());
+        if let Some(init) = init {
+            validator = validator.init(init);
+        }
+        if let Some(normalization) = normalization {
+            validator = validator.normalization(normalization);
+        }
+        for event in &self.events {
+            validator.event(event);
+        }
+        validator.finish()
+    }
+}
+
+impl<R: BufRead> EventrecordReader<R> {
+    /// Read all remaining events and check the record for consistency
+    ///
+    /// Events that have already been read are included in the counts,
+    /// but not checked otherwise. Only the number of events is checked
+    /// after skipping malformed events or seeking. Count mismatches
+    /// are part of the report even if the reader
+    /// [checks counts](Self::check_counts) itself. See [Validator] for
+    /// details.
+    pub fn validate(
+        &mut self,
+        init: Option<&Init>,
+        normalization: Option<&Normalization>,
+    ) -> Result<ValidationReport, ReadErr> {
+        let mut validator =
+            Validator::new(self.header().clone()).resume(self.counts());
+        if let Some(init) = init {
+            validator = validator.init(init);
+        }
+        if let Some(normalization) = normalization {
+            validator = validator.normalization(normalization);
+        }
+        let check_counts = self.set_check_counts(false);
+        let res = self.try_for_each(|event| {
+            validator.event(&event?);
+            Ok(())
+        });
+        self.set_check_counts(check_counts);
+        res.map(|_| validator.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{INIT, NORMALIZATION};
+
+    const RECORD: &str = r#"<Eventrecord nevents="2" nsubevents="2" nreweights="3" as="2" name="Bm">
+<e>
+<se w="-0.0002369763508" muR="91.16253934" muF="91.16253934">
+<p id="1,21"> 5780.608219,0,0,5780.608219 </p>
+<p id="1,21"> 334.3891359,0,0,-334.3891359 </p>
+<p id="0,6"> 357.9061187,-58.25473457,9.621341818,-307.9843429 </p>
+<p id="0,-6"> 5757.091237,58.25473457,-9.621341818,5754.203426 </p>
+<rw ch="0"> 0.8893243414,0.05144448245,-0.0002369763508 </rw>
+</se>
+</e>
+<e>
+<se w="-0.0004385904665" muR="517.0997809" muF="517.0997809">
+<p id="1,1"> 327.0442813,0,0,327.0442813 </p>
+<p id="1,-1"> 4344.52245,0,0,-4344.52245 </p>
+<p id="0,6"> 3334.580936,-386.757619,943.5205498,-3170.151619 </p>
+<p id="0,-6"> 1336.985795,386.757619,-943.5205498,-847.3265495 </p>
+<rw ch="1"> 0.05031450481,0.6683880692,-0.0004385904665 </rw>
+<rw ch="3"> 0.05031450481,0.6683880692,-0.0004385904665,0.1 </rw>
+</se>
+</e>
+</Eventrecord>
+"#;
+
+    #[test]
+    fn validate_record() {
+        let init: Init = quick_xml::de::from_str(INIT).unwrap();
+        let norm: Normalization =
+            quick_xml::de::from_str(NORMALIZATION).unwrap();
+        let record: Eventrecord = quick_xml::de::from_str(RECORD).unwrap();
+
+        assert!(record.validate(None, None).is_ok());
+        let report = record.validate(Some(&init), Some(&norm));
+        assert_eq!(
+            report.issues(),
+            [
+                Issue::UnknownChannel {
+                    event: 1,
+                    subevent: 0,
+                    channel: 3
+                },
+                Issue::NumLogCoeff {
+                    event: 1,
+                    subevent: 0,
+                    expected: 1,
+                    actual: 2
+                }
+            ]
+        );
+
+        let mut reader = EventrecordReader::new(RECORD.as_bytes()).unwrap();
+        let streamed = reader.validate(Some(&init), Some(&norm)).unwrap();
+        assert_eq!(streamed, report);
+    }
+
+    #[test]
+    fn validate_counts() {
+        let record = RECORD.replace(r#"nreweights="3""#, r#"nreweights="2""#);
+        let mut reader = EventrecordReader::new(record.as_bytes()).unwrap();
+        let report = reader.validate(None, None).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(
+            report.issues(),
+            [Issue::CountMismatch {
+                attribute: "nreweights",
+                declared: 2,
+                actual: 3
+            }]
+        );
+    }
+
+    #[test]
+    fn validate_after_reading() {
+        let mut reader = EventrecordReader::new(RECORD.as_bytes())
+            .unwrap()
+            .check_counts(true);
+        reader.next().unwrap().unwrap();
+        let init: Init = quick_xml::de::from_str(INIT).unwrap();
+        let norm: Normalization =
+            quick_xml::de::from_str(NORMALIZATION).unwrap();
+        let report = reader.validate(Some(&init), Some(&norm)).unwrap();
+        let record: Eventrecord = quick_xml::de::from_str(RECORD).unwrap();
+        assert_eq!(report, record.validate(Some(&init), Some(&norm)));
+
+        let record = RECORD.replace(r#"nevents="2""#, r#"nevents="3""#);
+        let mut reader = EventrecordReader::new(record.as_bytes())
+            .unwrap()
+            .check_counts(true);
+        reader.next().unwrap().unwrap();
+        let report = reader.validate(None, None).unwrap();
+        assert_eq!(
+            report.issues(),
+            [Issue::CountMismatch {
+                attribute: "nevents",
+                declared: 3,
+                actual: 2
+            }]
+        );
+    }
+}
@@ -0,0 +1,93 @@
+//! Versions of the STRIPPER XML format
+//!
+//! The version of a record is taken from the generator comment at the
+//! start of the record, e.g. `File generated with STRIPPER v0.1`.
+//! Records without such a comment, for example the ones written by
+//! this crate, are assumed to use the [latest](FormatVersion::LATEST)
+//! version, which is also the version used for writing. Records
+//! declaring an unknown version are read with the layout of the
+//! latest version.
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use crate::ParseErr;
+
+/// Version of the STRIPPER XML format
+#[derive(Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[non_exhaustive]
+pub enum FormatVersion {
+    /// STRIPPER v0.1
+    #[default]
+    V0_1,
+    /// A version not known to this crate, read like the latest one
+    Unknown(String),
+}
+
+impl FormatVersion {
+    /// The latest supported version
+    pub const LATEST: Self = Self::V0_1;
+
+    const NAMES: [(&'static str, Self); 1] = [("0.1", Self::V0_1)];
+
+    /// Detect the version from the comments at the start of a record
+    pub fn detect<'a>(comments: impl IntoIterator<Item = &'a str>) -> Self {
+        const MARKER: &str = "STRIPPER v";
+
+        for comment in comments {
+            let Some(pos) = comment.find(MARKER) else {
+                continue;
+            };
+            let version = comment[pos + MARKER.len()..]
+                .split_whitespace()
+                .next()
+                .unwrap_or_default();
+            return version
+                .parse()
+                .unwrap_or_else(|_| Self::Unknown(version.to_owned()));
+        }
+        Self::LATEST
+    }
+}
+
+impl FromStr for FormatVersion {
+    type Err = ParseErr;
+
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        Self::NAMES
+            .iter()
+            .find(|(name, _)| *name == version)
+            .map(|(_, version)| version.clone())
+            .ok_or_else(|| ParseErr::InvalidVersion(version.to_owned()))
+    }
+}
+
+impl Display for FormatVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Self::Unknown(version) = self {
+            return f.write_str(version);
+        }
+        let (name, _) = Self::NAMES.iter().find(|(_, v)| v == self).unwrap();
+        f.write_str(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_version() {
+        let comment =
+            "\nFile generated with STRIPPER v0.1 for online data base\n";
+        assert_eq!(FormatVersion::detect([comment]), FormatVersion::V0_1);
+        assert_eq!(FormatVersion::detect([]), FormatVersion::LATEST);
+        let unknown = FormatVersion::detect(["STRIPPER v9.9"]);
+        assert_eq!(unknown, FormatVersion::Unknown("9.9".to_owned()));
+        assert_eq!(unknown.to_string(), "9.9");
+        assert_eq!(FormatVersion::V0_1.to_string(), "0.1");
+        assert!(matches!(
+            "0.2".parse::<FormatVersion>(),
+            Err(ParseErr::InvalidVersion(_))
+        ));
+    }
+}